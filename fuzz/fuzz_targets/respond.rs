@@ -0,0 +1,14 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+
+use filbase::testing::TestServer;
+
+thread_local! {
+    // Shared between runs, so that sequences of requests build up state.
+    static SERVER: TestServer = TestServer::new().unwrap();
+}
+
+fuzz_target!(|data: &[u8]| {
+    SERVER.with(|server| filbase::fuzz::respond(server, data));
+});
@@ -1,18 +1,42 @@
+use failure::Fail;
 use filecoin_proofs::api::sector_builder::metadata::*;
 use serde::{Deserialize, Serialize};
 
+/// The version of the protocol, exchanged in the handshake which opens every connection.
+///
+/// Version 2 encodes binary fields as CBOR byte strings instead of arrays of integers, including
+/// those of the sector metadata defined by `filecoin-proofs`, and added the handshake.
+pub const PROTOCOL_VERSION: u32 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Request {
+    /// Must be the first request on a connection.
+    Handshake {
+        protocol_version: u32,
+    },
+    Status,
+
     // -- Post
     PostGenerate {
+        #[serde(with = "crate::wire::arrays")]
         comm_rs: Vec<[u8; 32]>,
+        #[serde(with = "crate::wire::array")]
+        challenge_seed: [u8; 32],
+    },
+    /// Generate a PoSt over the daemon's own sealed sectors.
+    PostGenerateSealed {
+        sectors: SectorFilter,
+        #[serde(with = "crate::wire::array")]
         challenge_seed: [u8; 32],
     },
     PostVerify {
         sector_size: u64,
         proof_partitions: u8,
+        #[serde(with = "crate::wire::arrays")]
         comm_rs: Vec<[u8; 32]>,
+        #[serde(with = "crate::wire::array")]
         challenge_seed: [u8; 32],
+        #[serde(with = "crate::wire::vecs")]
         proofs: Vec<Vec<u8>>,
         faults: Vec<u64>,
     },
@@ -20,13 +44,22 @@ pub enum Request {
     // -- Seal
     SealVerify {
         sector_size: u64,
+        #[serde(with = "crate::wire::array")]
         comm_r: [u8; 32],
+        #[serde(with = "crate::wire::array")]
         comm_d: [u8; 32],
+        #[serde(with = "crate::wire::array")]
         comm_r_star: [u8; 32],
+        #[serde(with = "crate::wire::array")]
         prover_id: [u8; 31],
+        #[serde(with = "crate::wire::array")]
         sector_id: [u8; 31],
+        #[serde(with = "serde_bytes")]
         proof: Vec<u8>,
     },
+    /// Verify one of our own sealed sectors, using its stored metadata.
+    SealVerifySector(u64),
+    /// Seal every staged sector holding pieces. The sector builder can not seal a single sector.
     SealAllStaged,
     SealStatus(u64),
 
@@ -34,6 +67,20 @@ pub enum Request {
     SectorSize(u64),
     SectorListSealed,
     SectorListStaged,
+    /// How full the staged sectors are, optionally checking if a piece of the given size fits.
+    SectorUsage(Option<u64>),
+    /// Check the sealed sectors for faults, `checksums` also compares them against the checksums
+    /// taken when they were sealed. Recomputing the replica commitments is not supported.
+    SectorCheck {
+        checksums: bool,
+    },
+    SectorMove(SectorMove),
+    /// Write a sealed sector into an archive at `path`, on the host of the daemon. Fails if `path`
+    /// exists. Archives can not be imported into a daemon.
+    SectorExport {
+        sector_id: u64,
+        path: String,
+    },
 
     // -- Piece
     PieceAdd {
@@ -42,31 +89,502 @@ pub enum Request {
         path: String,
     },
     PieceRead(String),
+    /// List the stored pieces, ordered by key.
+    PieceList {
+        prefix: Option<String>,
+        offset: u64,
+        limit: u64,
+        include_removed: bool,
+    },
+    PieceInfo(String),
+    /// Mark a piece in a sealed sector as expired. Staged pieces can not be taken back, as the
+    /// sector builder has no way to drop their bytes.
+    PieceExpire(String),
+
+    // -- Backup
+    /// Write a backup archive of the metadata, the prover id and the settings to `path`, on the
+    /// host of the daemon. Fails if `path` exists.
+    BackupCreate {
+        path: String,
+    },
+
+    // -- Config
+    /// Reload the config file and the environment.
+    ConfigReload,
+}
+
+impl Request {
+    /// Whether sending the request again has no further effect, so that it can be retried even
+    /// if the daemon may have received it.
+    ///
+    /// Requests reading whole sectors, like PoSts, sector checks and backups, are not retried,
+    /// even though they are safe to repeat, as repeating them is expensive.
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            Request::Handshake { .. }
+            | Request::Status
+            | Request::PostVerify { .. }
+            | Request::SealVerify { .. }
+            | Request::SealVerifySector(_)
+            | Request::SealStatus(_)
+            | Request::SectorSize(_)
+            | Request::SectorListSealed
+            | Request::SectorListStaged
+            | Request::SectorUsage(_)
+            | Request::PieceRead(_)
+            | Request::PieceList { .. }
+            | Request::PieceInfo(_)
+            | Request::ConfigReload => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Response {
+    /// The protocol version of the daemon. It closes the connection, if the version of the
+    /// handshake differs.
+    Handshake {
+        protocol_version: u32,
+    },
+    Status(Status),
+
     // -- Post
     PostGenerate {
+        #[serde(with = "crate::wire::vecs")]
         proofs: Vec<Vec<u8>>,
         faults: Vec<u64>,
     },
+    /// Contains all inputs used, so that the proofs can be verified independently.
+    PostGenerateSealed {
+        sector_size: u64,
+        proof_partitions: u8,
+        sector_ids: Vec<u64>,
+        #[serde(with = "crate::wire::arrays")]
+        comm_rs: Vec<[u8; 32]>,
+        #[serde(with = "crate::wire::array")]
+        challenge_seed: [u8; 32],
+        #[serde(with = "crate::wire::vecs")]
+        proofs: Vec<Vec<u8>>,
+        faults: Vec<u64>,
+        /// Sectors left out of the proof, as the integrity checks found them faulty.
+        #[serde(default)]
+        excluded: Vec<u64>,
+    },
     PostVerify(bool),
 
     // -- Seal
     SealVerify(bool),
+    SealVerifySector(SealVerifyResult),
     SealAllStaged,
-    SealStatus(SealStatus),
+    SealStatus(#[serde(with = "crate::wire::seal_status")] SealStatus),
 
     // -- Sector
     SectorSize(u64),
-    SectorListSealed(Vec<SealedSectorMetadata>),
-    SectorListStaged(Vec<StagedSectorMetadata>),
+    SectorListSealed(Vec<SealedSector>),
+    SectorListStaged(#[serde(with = "crate::wire::staged_sectors")] Vec<StagedSectorMetadata>),
+    SectorUsage(SectorUsage),
+    SectorCheck(Vec<SectorFault>),
+    SectorMove(Vec<SectorMoved>),
+    SectorExport,
 
     // -- Piece
-    PieceAdd(u64),
-    PieceRead(Vec<u8>),
+    PieceAdd {
+        sector_id: u64,
+        #[serde(with = "crate::wire::array")]
+        comm_p: [u8; 32],
+    },
+    PieceRead(#[serde(with = "serde_bytes")] Vec<u8>),
+    PieceList {
+        /// The number of pieces matching the prefix, ignoring the offset and limit.
+        total: u64,
+        pieces: Vec<PieceInfo>,
+    },
+    PieceInfo(PieceInfo),
+    PieceExpire,
+
+    // -- Backup
+    BackupCreate,
 
+    // -- Config
+    ConfigReload(ConfigReload),
+
+    /// The request was rejected, as there is not enough disk space to complete it.
+    InsufficientSpace(InsufficientSpace),
     /// Used for `Err(some_error)` return types.
     Err(String),
 }
+
+/// The outcome of verifying a sealed sector against its stored metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SealVerifyResult {
+    Valid,
+    /// Verification failed. `field` is only named when the sector is not sealed, or its proof or
+    /// a commitment is missing from the metadata. A proof which does not verify names no field:
+    /// the proofs only tell that the inputs do not match together, not which of them is wrong.
+    Invalid {
+        field: Option<String>,
+        reason: String,
+    },
+}
+
+/// Selects a subset of the sealed sectors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SectorFilter {
+    All,
+    Ids(Vec<u64>),
+    /// All sector ids in `start..=end`.
+    Range {
+        start: u64,
+        end: u64,
+    },
+}
+
+impl SectorFilter {
+    pub fn matches(&self, sector_id: u64) -> bool {
+        match self {
+            SectorFilter::All => true,
+            SectorFilter::Ids(ids) => ids.contains(&sector_id),
+            SectorFilter::Range { start, end } => *start <= sector_id && sector_id <= *end,
+        }
+    }
+}
+
+/// The state of the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    #[serde(with = "crate::wire::array")]
+    pub prover_id: [u8; 31],
+    pub sector_size: u64,
+    pub max_user_bytes: u64,
+    /// The last decision of the sealing policy, if it is enabled.
+    pub sealing_policy: Option<PolicyDecision>,
+    pub disks: Vec<DiskStatus>,
+    /// The faults found by the last integrity check.
+    pub faults: Vec<SectorFault>,
+}
+
+/// A sealed sector which failed the integrity check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectorFault {
+    pub sector_id: u64,
+    pub reason: String,
+    /// When the fault was found, in seconds since the unix epoch.
+    pub detected_at: u64,
+}
+
+/// The free space of one of the directories used by the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskStatus {
+    pub path: String,
+    pub available: u64,
+    pub total: u64,
+    pub level: DiskLevel,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DiskLevel {
+    Ok,
+    /// Below the configured warning threshold.
+    Warning,
+    /// Below the configured critical threshold.
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Fail)]
+#[fail(
+    display = "insufficient space in {}: {} bytes required, {} bytes available",
+    path, required, available
+)]
+pub struct InsufficientSpace {
+    pub path: String,
+    pub required: u64,
+    pub available: u64,
+}
+
+/// A decision made by the automatic sealing policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyDecision {
+    /// When the decision was made, in seconds since the unix epoch.
+    pub at: u64,
+    /// The staged sectors which are due for sealing.
+    pub due: Vec<u64>,
+    /// Whether sealing was started.
+    pub seal: bool,
+    /// The staged sectors sealing was started for. All open sectors are sealed together, so
+    /// this includes those which are not due.
+    #[serde(default)]
+    pub started: Vec<u64>,
+    pub reason: String,
+}
+
+/// Where a piece is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PieceInfo {
+    pub key: String,
+    pub num_bytes: u64,
+    pub sector_id: u64,
+    pub sector_state: SectorState,
+    /// When the piece was added, in seconds since the unix epoch. Unknown for pieces added
+    /// before filbase started tracking it.
+    pub added_at: Option<u64>,
+    /// The commitment recorded when the piece was added, if known.
+    #[serde(with = "crate::wire::option_array")]
+    pub comm_p: Option<[u8; 32]>,
+    pub removed: Option<PieceRemoval>,
+}
+
+/// Why a piece is no longer available.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PieceRemoval {
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SectorState {
+    Staged,
+    Sealing,
+    Sealed,
+    Failed(String),
+}
+
+/// The fill state of all staged sectors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectorUsage {
+    pub sectors: Vec<StagedSectorUsage>,
+    pub max_num_staged_sectors: u8,
+    /// The capacity of a single sector.
+    pub max_user_bytes: u64,
+    pub used_bytes: u64,
+    /// Free bytes in the open staged sectors, and in the sectors which can still be created.
+    pub free_bytes: u64,
+    /// Whether the requested piece fits into any open or new sector.
+    pub fits: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StagedSectorUsage {
+    pub sector_id: u64,
+    pub state: SectorState,
+    pub used_bytes: u64,
+    pub free_bytes: u64,
+    pub num_pieces: u64,
+    /// The age of the oldest piece, in seconds, if known.
+    pub age: Option<u64>,
+    /// Whether the requested piece fits, only for sectors which are still open.
+    pub fits: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedSector {
+    #[serde(with = "crate::wire::sealed_sector")]
+    pub meta: SealedSectorMetadata,
+    /// The storage path the sector is stored in.
+    pub path: String,
+}
+
+/// Which sealed sectors to move to another storage path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SectorMove {
+    Sector {
+        sector_id: u64,
+        to: String,
+    },
+    /// Move all sectors off this path, placing them according to the placement policy.
+    Drain {
+        from: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SectorMoved {
+    pub sector_id: u64,
+    pub from: String,
+    pub to: String,
+}
+
+/// The settings changed by a reload.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConfigReload {
+    /// Settings now in effect.
+    pub applied: Vec<String>,
+    /// Settings which only take effect after a restart of the daemon.
+    pub ignored: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sector_base::api::bytes_amount::UnpaddedBytesAmount;
+
+    /// The messages as encoded before protocol version 2.
+    #[derive(Serialize)]
+    enum V1Request {
+        PostVerify {
+            sector_size: u64,
+            proof_partitions: u8,
+            comm_rs: Vec<[u8; 32]>,
+            challenge_seed: [u8; 32],
+            proofs: Vec<Vec<u8>>,
+            faults: Vec<u64>,
+        },
+        SealVerify {
+            sector_size: u64,
+            comm_r: [u8; 32],
+            comm_d: [u8; 32],
+            comm_r_star: [u8; 32],
+            prover_id: [u8; 31],
+            sector_id: [u8; 31],
+            proof: Vec<u8>,
+        },
+    }
+
+    #[derive(Serialize)]
+    enum V1Response {
+        PieceRead(Vec<u8>),
+        SealStatus(SealStatus),
+        SectorListSealed(Vec<V1SealedSector>),
+        SectorListStaged(Vec<StagedSectorMetadata>),
+    }
+
+    #[derive(Serialize)]
+    struct V1SealedSector {
+        meta: SealedSectorMetadata,
+        path: String,
+    }
+
+    fn post_verify() -> (Request, V1Request) {
+        let comm_rs = vec![[0xab; 32]; 10];
+        let proofs = vec![vec![0xcd; 192]; 2];
+        let request = Request::PostVerify {
+            sector_size: 1 << 28,
+            proof_partitions: 2,
+            comm_rs: comm_rs.clone(),
+            challenge_seed: [0xef; 32],
+            proofs: proofs.clone(),
+            faults: vec![],
+        };
+        let v1 = V1Request::PostVerify {
+            sector_size: 1 << 28,
+            proof_partitions: 2,
+            comm_rs,
+            challenge_seed: [0xef; 32],
+            proofs,
+            faults: vec![],
+        };
+        (request, v1)
+    }
+
+    fn seal_verify() -> (Request, V1Request) {
+        let request = Request::SealVerify {
+            sector_size: 1 << 28,
+            comm_r: [0xab; 32],
+            comm_d: [0xbc; 32],
+            comm_r_star: [0xcd; 32],
+            prover_id: [0xde; 31],
+            sector_id: [0xef; 31],
+            proof: vec![0xfa; 384],
+        };
+        let v1 = V1Request::SealVerify {
+            sector_size: 1 << 28,
+            comm_r: [0xab; 32],
+            comm_d: [0xbc; 32],
+            comm_r_star: [0xcd; 32],
+            prover_id: [0xde; 31],
+            sector_id: [0xef; 31],
+            proof: vec![0xfa; 384],
+        };
+        (request, v1)
+    }
+
+    fn sealed_meta(sector_id: u64) -> SealedSectorMetadata {
+        SealedSectorMetadata {
+            sector_id,
+            sector_access: format!("sealed-{}", sector_id),
+            pieces: vec![PieceMetadata {
+                piece_key: "piece".into(),
+                num_bytes: UnpaddedBytesAmount(1016),
+            }],
+            comm_r_star: [0xab; 32],
+            comm_r: [0xbc; 32],
+            comm_d: [0xcd; 32],
+            proof: vec![0xde; 384],
+            blake2b_checksum: vec![0xef; 64],
+            len: 1024,
+        }
+    }
+
+    fn seal_status() -> (Response, V1Response) {
+        let status = SealStatus::Sealed(Box::new(sealed_meta(1)));
+        (
+            Response::SealStatus(status.clone()),
+            V1Response::SealStatus(status),
+        )
+    }
+
+    fn sector_list_sealed() -> (Response, V1Response) {
+        let response = Response::SectorListSealed(vec![SealedSector {
+            meta: sealed_meta(1),
+            path: "/sealed".into(),
+        }]);
+        let v1 = V1Response::SectorListSealed(vec![V1SealedSector {
+            meta: sealed_meta(1),
+            path: "/sealed".into(),
+        }]);
+        (response, v1)
+    }
+
+    fn sector_list_staged() -> (Response, V1Response) {
+        let staged = vec![StagedSectorMetadata {
+            sector_id: 1,
+            sector_access: "staged-1".into(),
+            pieces: vec![],
+            seal_status: SealStatus::Sealed(Box::new(sealed_meta(1))),
+        }];
+        (
+            Response::SectorListStaged(staged.clone()),
+            V1Response::SectorListStaged(staged),
+        )
+    }
+
+    #[test]
+    fn wire_size() {
+        for (request, v1) in vec![post_verify(), seal_verify()] {
+            let size = serde_cbor::to_vec(&request).unwrap().len();
+            let v1_size = serde_cbor::to_vec(&v1).unwrap().len();
+            assert!(size * 10 < v1_size * 6, "{} vs {} bytes", size, v1_size);
+        }
+
+        let data = vec![0xab; 64 * 1024];
+        let size = serde_cbor::to_vec(&Response::PieceRead(data.clone()))
+            .unwrap()
+            .len();
+        let v1_size = serde_cbor::to_vec(&V1Response::PieceRead(data))
+            .unwrap()
+            .len();
+        assert!(size <= 64 * 1024 + 32, "{} bytes", size);
+        assert!(v1_size >= 2 * 64 * 1024, "{} bytes", v1_size);
+
+        for (response, v1) in vec![seal_status(), sector_list_sealed(), sector_list_staged()] {
+            let size = serde_cbor::to_vec(&response).unwrap().len();
+            let v1_size = serde_cbor::to_vec(&v1).unwrap().len();
+            assert!(size * 10 < v1_size * 6, "{} vs {} bytes", size, v1_size);
+        }
+    }
+
+    #[test]
+    fn decode_v1() {
+        for (request, v1) in vec![post_verify(), seal_verify()] {
+            let encoded = serde_cbor::to_vec(&v1).unwrap();
+            let decoded: Request = serde_cbor::from_slice(&encoded).unwrap();
+            assert_eq!(format!("{:?}", request), format!("{:?}", decoded));
+        }
+
+        for (response, v1) in vec![seal_status(), sector_list_sealed(), sector_list_staged()] {
+            let encoded = serde_cbor::to_vec(&v1).unwrap();
+            let decoded: Response = serde_cbor::from_slice(&encoded).unwrap();
+            assert_eq!(format!("{:?}", response), format!("{:?}", decoded));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
 
 pub fn get_matches() -> clap::ArgMatches<'static> {
     let mut app = App::new("Filecoin Base")
@@ -9,14 +9,29 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
             Arg::with_name("config")
                 .long("config")
                 .short("c")
+                .help("The config file to load, defaults to filbase.config.toml if it exists. Settings can be overridden with FILBASE_* environment variables, e.g. FILBASE_PORT.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("host")
+                .long("host")
+                .help("The host the daemon listens on and the client connects to, overrides the config")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("port")
+                .long("port")
+                .help("The port the daemon listens on and the client connects to, overrides the config")
+                .takes_value(true)
+                .global(true),
+        )
         .subcommand(
             SubCommand::with_name("daemon").about("Starts the daemon")
                 .arg(
                     Arg::with_name("prover-id")
                         .long("prover-id")
-                        .help("The id of the prover, encoded as hex (31 bytes)")
+                        .help("The id of the prover, encoded as hex (31 bytes). Defaults to the one stored in the metadata, or a new random one.")
                         .takes_value(true)
                 )
                 .arg(
@@ -34,6 +49,7 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                         .default_value("0")
                 )
         )
+        .subcommand(SubCommand::with_name("status").about("Show the status of the daemon"))
         .subcommand(
             SubCommand::with_name("post")
                 .setting(AppSettings::ArgRequiredElseHelp)
@@ -45,13 +61,61 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                                 .help("A list of hex encoded comm_rs (each 32 bytes)")
                                 .use_delimiter(true)
                                 .takes_value(true)
-                                .required(true),
+                                .required_unless("sealed"),
+                        )
+                        .arg(
+                            Arg::with_name("sealed")
+                                .long("sealed")
+                                .help("Use the comm_rs of the sealed sectors of the daemon")
+                                .conflicts_with("comm-rs"),
+                        )
+                        .arg(
+                            Arg::with_name("sector-ids")
+                                .long("sector-ids")
+                                .help("Only use the sealed sectors with these ids")
+                                .use_delimiter(true)
+                                .takes_value(true)
+                                .requires("sealed")
+                                .conflicts_with("sector-range"),
+                        )
+                        .arg(
+                            Arg::with_name("sector-range")
+                                .long("sector-range")
+                                .help("Only use the sealed sectors in this inclusive range of ids, e.g. 4..10")
+                                .takes_value(true)
+                                .requires("sealed"),
                         )
                         .arg(
                             Arg::with_name("challenge-seed")
                                 .long("challenge-seed")
                                 .help("Hex encoded seed (32 bytes)")
-                                .takes_value(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("challenge-seed-file")
+                                .long("challenge-seed-file")
+                                .help("Read the seed from a file, containing either 32 raw bytes or their hex encoding")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("challenge-seed-string")
+                                .long("challenge-seed-string")
+                                .help("Use the SHA256 hash of this string as seed")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("random-challenge-seed")
+                                .long("random-challenge-seed")
+                                .help("Use a random seed"),
+                        )
+                        .group(
+                            ArgGroup::with_name("seed")
+                                .args(&[
+                                    "challenge-seed",
+                                    "challenge-seed-file",
+                                    "challenge-seed-string",
+                                    "random-challenge-seed",
+                                ])
                                 .required(true),
                         ),
                 )
@@ -63,6 +127,13 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                                 .takes_value(true)
                                 .required(true),
                         )
+                        .arg(
+                            Arg::with_name("proof-partitions")
+                                .long("proof-partitions")
+                                .help("The number of PoSt proof partitions")
+                                .takes_value(true)
+                                .default_value("2"),
+                        )
                         .arg(
                             Arg::with_name("comm-rs")
                                 .long("comm-rs")
@@ -91,55 +162,54 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                                 .long("faults")
                                 .help("A list of sector ids who faulted")
                                 .use_delimiter(true)
-                                .takes_value(true)
-                                .required(true),
+                                .takes_value(true),
                         ),
                 ),
         )
         .subcommand(
             SubCommand::with_name("seal")
                 .setting(AppSettings::ArgRequiredElseHelp)
-                .subcommand(SubCommand::with_name("generate"))
+                .subcommand(SubCommand::with_name("generate").about("Seal all staged sectors"))
                 .subcommand(
                     SubCommand::with_name("verify")
+                        .about("Verify a seal proof. When only --sector-id is given, the stored metadata of that sector is used, and a failure names the field only if it is missing from the metadata.")
                         .arg(
                             Arg::with_name("sector-size")
                                 .long("sector-size")
-                                .takes_value(true)
-                                .required(true),
+                                .takes_value(true),
                         )
                         .arg(
                             Arg::with_name("comm-r")
                                 .long("comm-r")
                                 .help("Hex encoded comm_r (32 bytes)")
                                 .takes_value(true)
-                                .required(true),
+                                .requires_all(&["sector-size", "comm-d", "comm-r-star", "prover-id", "proof"]),
                         )
                         .arg(
                             Arg::with_name("comm-d")
                                 .long("comm-d")
                                 .help("Hex encoded comm_d (32 bytes)")
                                 .takes_value(true)
-                                .required(true),
+                                .requires("comm-r"),
                         )
                         .arg(
                             Arg::with_name("comm-r-star")
                                 .long("comm-r-star")
                                 .help("Hex encoded comm_r_star (32 bytes)")
                                 .takes_value(true)
-                                .required(true),
+                                .requires("comm-r"),
                         )
                         .arg(
                             Arg::with_name("prover-id")
                                 .long("prover-id")
                                 .help("Hex encoded prover ID (31 bytes)")
                                 .takes_value(true)
-                                .required(true),
+                                .requires("comm-r"),
                         )
                         .arg(
                             Arg::with_name("sector-id")
                                 .long("sector-id")
-                                .help("Hex encoded sector ID (31 bytes)")
+                                .help("Hex encoded sector ID (31 bytes), or the numeric sector ID when verifying by stored metadata")
                                 .takes_value(true)
                                 .required(true),
                         )
@@ -148,7 +218,7 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                                 .long("proof")
                                 .help("Hex encoded proof")
                                 .takes_value(true)
-                                .required(true),
+                                .requires("comm-r"),
                         ),
                 )
                 .subcommand(
@@ -170,7 +240,74 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                         .arg(Arg::with_name("SIZE").takes_value(true).required(true)),
                 )
                 .subcommand(SubCommand::with_name("list-sealed"))
-                .subcommand(SubCommand::with_name("list-staged")),
+                .subcommand(SubCommand::with_name("list-staged"))
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("Check that the files of all sealed sectors exist and have the right size. Replica commitments are not recomputed.")
+                        .arg(
+                            Arg::with_name("checksums")
+                                .long("checksums")
+                                .help("Also read all sealed sectors and compare them against the checksums taken when they were sealed"),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .help("Write the faulty sector ids to this file, in the format expected by `post verify --faults`")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("move")
+                        .about("Move sealed sectors to another storage path")
+                        .arg(
+                            Arg::with_name("sector-id")
+                                .long("sector-id")
+                                .takes_value(true)
+                                .requires("to")
+                                .required_unless("drain"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .help("The storage path to move the sector to")
+                                .takes_value(true)
+                                .requires("sector-id"),
+                        )
+                        .arg(
+                            Arg::with_name("drain")
+                                .long("drain")
+                                .help("Move all sectors off this storage path")
+                                .takes_value(true)
+                                .conflicts_with_all(&["sector-id", "to"]),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Write a sealed sector, its metadata and proof into an archive. Importing archives is not supported.")
+                        .arg(
+                            Arg::with_name("sector-id")
+                                .long("sector-id")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .help("Where the daemon writes the archive, must not exist yet")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("usage")
+                        .about("Show how full the staged sectors are")
+                        .arg(
+                            Arg::with_name("piece-size")
+                                .long("piece-size")
+                                .help("Check if a piece of this many bytes would fit")
+                                .takes_value(true),
+                        ),
+                ),
         )
         .subcommand(
             SubCommand::with_name("piece")
@@ -180,7 +317,7 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                     SubCommand::with_name("add")
                         .about("Add a new piece")
                         .arg(
-                            Arg::with_name("key")
+                            Arg::with_name("KEY")
                                 .takes_value(true)
                                 .required(true),
                         )
@@ -191,10 +328,148 @@ pub fn get_matches() -> clap::ArgMatches<'static> {
                                 .takes_value(true)
 
                         )
+                        .arg(
+                            Arg::with_name("verify")
+                                .long("verify")
+                                .help("Compute comm_p locally and check it against the one stored by the daemon. Requires PATH to be readable locally.")
+                        )
+                        .arg(
+                            Arg::with_name("sector-size")
+                                .long("sector-size")
+                                .help("The sector size of the daemon, in bytes. Defaults to the one the daemon reports.")
+                                .takes_value(true)
+                        )
+                        .arg(Arg::with_name("PATH").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Add all files of a directory, or all pieces listed in a CSV or JSON manifest")
+                        .long_about(
+                            "Add all files of a directory, or all pieces listed in a CSV or JSON manifest.\n\n\
+                             Files in a directory use their file name as key. A CSV manifest has lines of \
+                             `key,path[,size]`, a JSON manifest is a list of `{\"key\", \"path\", \"size\"}` objects. \
+                             Keys already known to the daemon are skipped, so an interrupted import can be resumed \
+                             by running it again.",
+                        )
+                        .arg(Arg::with_name("SOURCE").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("commit")
+                        .about("Compute the piece commitment (comm_p) of a file locally, the daemon is only asked for its sector size")
+                        .arg(
+                            Arg::with_name("amount")
+                                .help("The size of the piece in bytes, if not provided the whole file is assumed.")
+                                .long("amount")
+                                .takes_value(true)
+                        )
+                        .arg(
+                            Arg::with_name("sector-size")
+                                .long("sector-size")
+                                .help("The sector size to check the piece against, in bytes. Defaults to the one the daemon reports.")
+                                .takes_value(true)
+                        )
                         .arg(Arg::with_name("PATH").required(true)),
                 )
                 .subcommand(
                     SubCommand::with_name("read").arg(Arg::with_name("KEY").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the stored pieces")
+                        .arg(
+                            Arg::with_name("prefix")
+                                .long("prefix")
+                                .help("Only list pieces whose key starts with this prefix")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("offset")
+                                .long("offset")
+                                .help("The number of pieces to skip")
+                                .takes_value(true)
+                                .default_value("0"),
+                        )
+                        .arg(
+                            Arg::with_name("limit")
+                                .long("limit")
+                                .help("The maximum number of pieces to list")
+                                .takes_value(true)
+                                .default_value("100"),
+                        )
+                        .arg(
+                            Arg::with_name("all")
+                                .long("all")
+                                .help("Include expired pieces"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("expire")
+                        .about("Mark a piece in a sealed sector as expired")
+                        .arg(Arg::with_name("KEY").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("info")
+                        .about("Show where a piece is stored")
+                        .arg(Arg::with_name("KEY").required(true)),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("backup")
+                .about("Backup and restore the daemon metadata")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Write a backup of the metadata, prover id and settings of the running daemon")
+                        .arg(
+                            Arg::with_name("OUTPUT")
+                                .help("Where the daemon writes the archive, must not exist yet")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Restore a backup into the configured metadata_dir, the daemon must be stopped")
+                        .arg(Arg::with_name("ARCHIVE").required(true))
+                        .arg(
+                            Arg::with_name("settings")
+                                .long("settings")
+                                .help("Where to write the backed up settings")
+                                .takes_value(true)
+                                .default_value("filbase.config.restored.json"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Replace a non empty metadata_dir"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Manage the configuration")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("init")
+                        .about("Write a config file with the default settings")
+                        .arg(Arg::with_name("PATH").default_value("filbase.config.toml"))
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Replace an existing file"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show the active settings and where each value comes from"),
+                )
+                .subcommand(
+                    SubCommand::with_name("check")
+                        .about("Validate a config file, without the environment overrides")
+                        .arg(Arg::with_name("FILE").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("reload")
+                        .about("Reload the settings of the running daemon, same as sending it SIGHUP"),
                 ),
         );
 
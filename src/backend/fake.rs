@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use failure::{bail, format_err, Error};
+use filecoin_proofs::api::safe as fil_api;
+use filecoin_proofs::api::sector_builder::metadata::{
+    PieceMetadata, SealStatus, SealedSectorMetadata, StagedSectorMetadata,
+};
+use sector_base::api::bytes_amount::UnpaddedBytesAmount;
+use sha2::{Digest, Sha256};
+
+use super::{sector_id_as_bytes, PoStOutput, SectorBackend};
+use crate::integrity;
+use crate::policy;
+
+/// A deterministic in-memory backend for tests.
+///
+/// Sectors are sealed at once and the commitments and proofs are hashes of their inputs, so they
+/// only verify with another `FakeBackend`. Sealed sectors are written to the `sealed_sector_dir`,
+/// so that integrity checks, placement and exports work on them. Nothing survives a restart.
+pub struct FakeBackend {
+    sector_size: u64,
+    post_partitions: u8,
+    max_num_staged_sectors: u8,
+    prover_id: [u8; 31],
+    sealed_sector_dir: PathBuf,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    last_used_id: u64,
+    staged: Vec<StagedSectorMetadata>,
+    sealed: Vec<SealedSectorMetadata>,
+    pieces: HashMap<String, Vec<u8>>,
+}
+
+impl FakeBackend {
+    pub fn new<P: AsRef<Path>>(
+        sector_size: u64,
+        post_partitions: u8,
+        max_num_staged_sectors: u8,
+        last_used_id: u64,
+        prover_id: [u8; 31],
+        sealed_sector_dir: P,
+    ) -> Result<Self, Error> {
+        fs::create_dir_all(sealed_sector_dir.as_ref())?;
+
+        Ok(FakeBackend {
+            sector_size,
+            post_partitions,
+            max_num_staged_sectors,
+            prover_id,
+            sealed_sector_dir: sealed_sector_dir.as_ref().into(),
+            state: Mutex::new(State {
+                last_used_id,
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn max_user_bytes(&self) -> u64 {
+        fil_api::get_max_user_bytes_per_staged_sector(self.sector_size)
+    }
+
+    fn seal(
+        &self,
+        staged: &StagedSectorMetadata,
+        state: &State,
+    ) -> Result<SealedSectorMetadata, Error> {
+        let mut replica = Vec::with_capacity(self.sector_size as usize);
+        for piece in &staged.pieces {
+            replica.extend_from_slice(&state.pieces[&piece.piece_key]);
+        }
+        replica.resize(self.sector_size as usize, 0);
+
+        let path = self
+            .sealed_sector_dir
+            .join(format!("sealed-{}", staged.sector_id));
+        fs::write(&path, &replica)?;
+
+        let sector_id = sector_id_as_bytes(staged.sector_id);
+        let comm_d = hash(&[b"comm_d", &replica]);
+        let comm_r = hash(&[b"comm_r", &self.prover_id, &sector_id, &comm_d]);
+        let comm_r_star = hash(&[b"comm_r_star", &comm_r, &comm_d]);
+        let proof = seal_proof(
+            self.sector_size,
+            &comm_r,
+            &comm_d,
+            &comm_r_star,
+            &self.prover_id,
+            &sector_id,
+        );
+
+        Ok(SealedSectorMetadata {
+            sector_id: staged.sector_id,
+            sector_access: path.to_string_lossy().into(),
+            pieces: staged.pieces.clone(),
+            comm_r_star,
+            comm_r,
+            comm_d,
+            proof: proof.to_vec(),
+            blake2b_checksum: integrity::blake2b_checksum(&path)?,
+            len: replica.len() as u64,
+        })
+    }
+}
+
+impl SectorBackend for FakeBackend {
+    fn add_piece(&self, key: &str, amount: u64, path: &str) -> Result<u64, Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.pieces.contains_key(key) {
+            bail!("duplicate piece key: {}", key);
+        }
+        if amount > self.max_user_bytes() {
+            bail!("piece of {} bytes does not fit into a sector", amount);
+        }
+
+        let mut data = Vec::new();
+        fs::File::open(path)?.take(amount).read_to_end(&mut data)?;
+        if (data.len() as u64) < amount {
+            bail!("{} has less than {} bytes", path, amount);
+        }
+
+        let max_user_bytes = self.max_user_bytes();
+        let open = state.staged.iter().position(|meta| match meta.seal_status {
+            SealStatus::Pending => policy::used_bytes(meta) + amount <= max_user_bytes,
+            _ => false,
+        });
+        let index = match open {
+            Some(index) => index,
+            None if state.staged.len() < self.max_num_staged_sectors as usize => {
+                state.last_used_id += 1;
+                let sector_id = state.last_used_id;
+                state.staged.push(StagedSectorMetadata {
+                    sector_id,
+                    sector_access: format!("staged-{}", sector_id),
+                    pieces: Vec::new(),
+                    seal_status: SealStatus::Pending,
+                });
+                state.staged.len() - 1
+            }
+            None => bail!(
+                "all {} staged sectors are full",
+                self.max_num_staged_sectors
+            ),
+        };
+
+        state.staged[index].pieces.push(PieceMetadata {
+            piece_key: key.into(),
+            num_bytes: UnpaddedBytesAmount(amount),
+        });
+        state.pieces.insert(key.into(), data);
+
+        Ok(state.staged[index].sector_id)
+    }
+
+    fn seal_all_staged(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        let staged = std::mem::replace(&mut state.staged, Vec::new());
+
+        for mut meta in staged {
+            let pending = match meta.seal_status {
+                SealStatus::Pending => !meta.pieces.is_empty(),
+                _ => false,
+            };
+            if !pending {
+                state.staged.push(meta);
+                continue;
+            }
+
+            match self.seal(&meta, &state) {
+                Ok(sealed) => state.sealed.push(sealed),
+                Err(err) => {
+                    meta.seal_status = SealStatus::Failed(err.to_string());
+                    state.staged.push(meta);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn seal_status(&self, sector_id: u64) -> Result<SealStatus, Error> {
+        let state = self.state.lock().unwrap();
+        if let Some(meta) = state.sealed.iter().find(|m| m.sector_id == sector_id) {
+            return Ok(SealStatus::Sealed(Box::new(meta.clone())));
+        }
+
+        state
+            .staged
+            .iter()
+            .find(|m| m.sector_id == sector_id)
+            .map(|m| m.seal_status.clone())
+            .ok_or_else(|| format_err!("unknown sector: {}", sector_id))
+    }
+
+    fn sealed_sectors(&self) -> Result<Vec<SealedSectorMetadata>, Error> {
+        Ok(self.state.lock().unwrap().sealed.clone())
+    }
+
+    fn staged_sectors(&self) -> Result<Vec<StagedSectorMetadata>, Error> {
+        Ok(self.state.lock().unwrap().staged.clone())
+    }
+
+    fn read_piece(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let state = self.state.lock().unwrap();
+        let sealed = state
+            .sealed
+            .iter()
+            .any(|meta| meta.pieces.iter().any(|p| p.piece_key == key));
+        if !sealed {
+            bail!("no sealed sector holds piece {}", key);
+        }
+
+        Ok(state.pieces[key].clone())
+    }
+
+    fn generate_post(
+        &self,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: &[u8; 32],
+    ) -> Result<PoStOutput, Error> {
+        let state = self.state.lock().unwrap();
+
+        let mut faults = Vec::new();
+        for comm_r in &comm_rs {
+            let meta = match state.sealed.iter().find(|m| m.comm_r == *comm_r) {
+                Some(meta) => meta,
+                None => bail!("unknown comm_r: {}", hex::encode(comm_r)),
+            };
+            if !Path::new(&meta.sector_access).exists() {
+                faults.push(meta.sector_id);
+            }
+        }
+
+        let proofs = (0..self.post_partitions)
+            .map(|partition| {
+                post_proof(
+                    self.sector_size,
+                    partition,
+                    &comm_rs,
+                    challenge_seed,
+                    &faults,
+                )
+                .to_vec()
+            })
+            .collect();
+
+        Ok(PoStOutput { proofs, faults })
+    }
+
+    fn verify_post(
+        &self,
+        sector_size: u64,
+        proof_partitions: u8,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: &[u8; 32],
+        proofs: Vec<Vec<u8>>,
+        faults: Vec<u64>,
+    ) -> Result<bool, Error> {
+        if proofs.len() != proof_partitions as usize {
+            return Ok(false);
+        }
+
+        Ok(proofs.iter().enumerate().all(|(partition, proof)| {
+            let expected = post_proof(
+                sector_size,
+                partition as u8,
+                &comm_rs,
+                challenge_seed,
+                &faults,
+            );
+            proof[..] == expected[..]
+        }))
+    }
+
+    fn verify_seal(
+        &self,
+        sector_size: u64,
+        comm_r: [u8; 32],
+        comm_d: [u8; 32],
+        comm_r_star: [u8; 32],
+        prover_id: &[u8; 31],
+        sector_id: &[u8; 31],
+        proof: Vec<u8>,
+    ) -> Result<bool, Error> {
+        let expected = seal_proof(
+            sector_size,
+            &comm_r,
+            &comm_d,
+            &comm_r_star,
+            prover_id,
+            sector_id,
+        );
+
+        Ok(proof[..] == expected[..])
+    }
+}
+
+fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.input(part);
+    }
+
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hasher.result());
+    out
+}
+
+fn seal_proof(
+    sector_size: u64,
+    comm_r: &[u8; 32],
+    comm_d: &[u8; 32],
+    comm_r_star: &[u8; 32],
+    prover_id: &[u8; 31],
+    sector_id: &[u8; 31],
+) -> [u8; 32] {
+    hash(&[
+        b"seal",
+        &sector_size.to_le_bytes(),
+        comm_r,
+        comm_d,
+        comm_r_star,
+        prover_id,
+        sector_id,
+    ])
+}
+
+fn post_proof(
+    sector_size: u64,
+    partition: u8,
+    comm_rs: &[[u8; 32]],
+    challenge_seed: &[u8; 32],
+    faults: &[u64],
+) -> [u8; 32] {
+    let comm_rs: Vec<u8> = comm_rs.iter().flat_map(|c| c.iter().cloned()).collect();
+    let faults: Vec<u8> = faults
+        .iter()
+        .flat_map(|f| f.to_le_bytes().to_vec())
+        .collect();
+
+    hash(&[
+        b"post",
+        &sector_size.to_le_bytes(),
+        &[partition],
+        &comm_rs,
+        challenge_seed,
+        &faults,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend(dir: &Path) -> FakeBackend {
+        FakeBackend::new(1024, 2, 2, 0, [1; 31], dir).unwrap()
+    }
+
+    fn piece(dir: &Path, name: &str, len: usize) -> String {
+        let path = dir.join(name);
+        fs::write(&path, vec![name.as_bytes()[0]; len]).unwrap();
+        path.to_string_lossy().into()
+    }
+
+    #[test]
+    fn seal_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend(dir.path());
+
+        let a = backend
+            .add_piece("a", 100, &piece(dir.path(), "a", 100))
+            .unwrap();
+        let b = backend
+            .add_piece("b", 100, &piece(dir.path(), "b", 100))
+            .unwrap();
+        assert_eq!(a, 1);
+        assert_eq!(a, b);
+        assert!(backend.read_piece("a").is_err());
+
+        backend.seal_all_staged().unwrap();
+        assert_eq!(backend.read_piece("b").unwrap(), vec![b'b'; 100]);
+
+        let sealed = backend.sealed_sectors().unwrap();
+        assert_eq!(sealed.len(), 1);
+        let meta = &sealed[0];
+        assert_eq!(meta.len, 1024);
+        assert!(backend
+            .verify_seal(
+                1024,
+                meta.comm_r,
+                meta.comm_d,
+                meta.comm_r_star,
+                &[1; 31],
+                &sector_id_as_bytes(meta.sector_id),
+                meta.proof.clone(),
+            )
+            .unwrap());
+        assert!(!backend
+            .verify_seal(
+                1024,
+                meta.comm_r,
+                meta.comm_d,
+                meta.comm_r_star,
+                &[2; 31],
+                &sector_id_as_bytes(meta.sector_id),
+                meta.proof.clone(),
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn full_sectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend(dir.path());
+        let path = piece(dir.path(), "x", 1000);
+
+        assert_eq!(backend.add_piece("x1", 1000, &path).unwrap(), 1);
+        assert_eq!(backend.add_piece("x2", 1000, &path).unwrap(), 2);
+        assert!(backend.add_piece("x3", 1000, &path).is_err());
+        assert!(backend.add_piece("x2", 10, &path).is_err());
+    }
+
+    #[test]
+    fn post() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend(dir.path());
+        backend
+            .add_piece("a", 10, &piece(dir.path(), "a", 10))
+            .unwrap();
+        backend.seal_all_staged().unwrap();
+
+        let comm_rs: Vec<_> = backend
+            .sealed_sectors()
+            .unwrap()
+            .iter()
+            .map(|m| m.comm_r)
+            .collect();
+        let out = backend.generate_post(comm_rs.clone(), &[7; 32]).unwrap();
+        assert_eq!(out.proofs.len(), 2);
+        assert!(out.faults.is_empty());
+
+        let valid = backend
+            .verify_post(
+                1024,
+                2,
+                comm_rs.clone(),
+                &[7; 32],
+                out.proofs.clone(),
+                vec![],
+            )
+            .unwrap();
+        assert!(valid);
+        let valid = backend
+            .verify_post(1024, 2, comm_rs, &[8; 32], out.proofs, vec![])
+            .unwrap();
+        assert!(!valid);
+    }
+}
@@ -0,0 +1,74 @@
+//! The sector storage and proofs behind the daemon.
+
+use failure::Error;
+use filecoin_proofs::api::sector_builder::metadata::{
+    SealStatus, SealedSectorMetadata, StagedSectorMetadata,
+};
+
+#[cfg(any(test, feature = "fake-backend"))]
+mod fake;
+mod proofs;
+
+#[cfg(any(test, feature = "fake-backend"))]
+pub use self::fake::FakeBackend;
+pub use self::proofs::ProofsBackend;
+
+/// The outcome of generating a PoSt.
+#[derive(Debug, Clone)]
+pub struct PoStOutput {
+    pub proofs: Vec<Vec<u8>>,
+    pub faults: Vec<u64>,
+}
+
+/// Stores pieces in sectors, seals them and generates and verifies their proofs.
+pub trait SectorBackend: Send {
+    /// Adds the first `amount` bytes of the file at `path` as a piece, returns its sector id.
+    fn add_piece(&self, key: &str, amount: u64, path: &str) -> Result<u64, Error>;
+
+    /// Seals all staged sectors holding pieces.
+    fn seal_all_staged(&self) -> Result<(), Error>;
+
+    fn seal_status(&self, sector_id: u64) -> Result<SealStatus, Error>;
+
+    fn sealed_sectors(&self) -> Result<Vec<SealedSectorMetadata>, Error>;
+
+    fn staged_sectors(&self) -> Result<Vec<StagedSectorMetadata>, Error>;
+
+    /// Reads a piece from the sealed sector holding it.
+    fn read_piece(&self, key: &str) -> Result<Vec<u8>, Error>;
+
+    fn generate_post(
+        &self,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: &[u8; 32],
+    ) -> Result<PoStOutput, Error>;
+
+    fn verify_post(
+        &self,
+        sector_size: u64,
+        proof_partitions: u8,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: &[u8; 32],
+        proofs: Vec<Vec<u8>>,
+        faults: Vec<u64>,
+    ) -> Result<bool, Error>;
+
+    #[allow(clippy::too_many_arguments)]
+    fn verify_seal(
+        &self,
+        sector_size: u64,
+        comm_r: [u8; 32],
+        comm_d: [u8; 32],
+        comm_r_star: [u8; 32],
+        prover_id: &[u8; 31],
+        sector_id: &[u8; 31],
+        proof: Vec<u8>,
+    ) -> Result<bool, Error>;
+}
+
+/// Converts a numeric sector id into the zero padded, little endian byte form used by the proofs.
+pub fn sector_id_as_bytes(sector_id: u64) -> [u8; 31] {
+    let mut bytes = [0u8; 31];
+    bytes[..8].copy_from_slice(&sector_id.to_le_bytes());
+    bytes
+}
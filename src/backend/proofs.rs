@@ -0,0 +1,123 @@
+use failure::Error;
+use filecoin_proofs::api::safe as fil_api;
+use filecoin_proofs::api::sector_builder::metadata::{
+    SealStatus, SealedSectorMetadata, StagedSectorMetadata,
+};
+use filecoin_proofs::api::sector_builder::SectorBuilder;
+use sector_base::api::porep_proof_partitions::PoRepProofPartitions;
+use sector_base::api::post_proof_partitions::PoStProofPartitions;
+use sector_base::api::sector_class::SectorClass;
+use sector_base::api::sector_size::SectorSize;
+
+use super::{PoStOutput, SectorBackend};
+use crate::settings::Settings;
+
+/// The sector builder and proofs of `filecoin_proofs`.
+pub struct ProofsBackend {
+    sb: SectorBuilder,
+}
+
+impl ProofsBackend {
+    /// Starts the sector builder, with the directories and partitions configured in `cfg`.
+    pub fn new(
+        cfg: &Settings,
+        sector_size: u64,
+        last_used_id: u64,
+        prover_id: [u8; 31],
+    ) -> Result<Self, Error> {
+        let sb = fil_api::init_sector_builder(
+            SectorClass(
+                SectorSize(sector_size),
+                PoRepProofPartitions(cfg.porep_partitions),
+                PoStProofPartitions(cfg.post_partitions),
+            ),
+            last_used_id,
+            &cfg.metadata_dir,
+            prover_id,
+            &cfg.sealed_sector_dir,
+            &cfg.staged_sector_dir,
+            cfg.max_num_staged_sectors,
+        )?;
+
+        Ok(ProofsBackend { sb })
+    }
+}
+
+impl SectorBackend for ProofsBackend {
+    fn add_piece(&self, key: &str, amount: u64, path: &str) -> Result<u64, Error> {
+        Ok(fil_api::add_piece(&self.sb, key, amount, path)?)
+    }
+
+    fn seal_all_staged(&self) -> Result<(), Error> {
+        Ok(fil_api::seal_all_staged_sectors(&self.sb)?)
+    }
+
+    fn seal_status(&self, sector_id: u64) -> Result<SealStatus, Error> {
+        Ok(fil_api::get_seal_status(&self.sb, sector_id)?)
+    }
+
+    fn sealed_sectors(&self) -> Result<Vec<SealedSectorMetadata>, Error> {
+        Ok(fil_api::get_sealed_sectors(&self.sb)?)
+    }
+
+    fn staged_sectors(&self) -> Result<Vec<StagedSectorMetadata>, Error> {
+        Ok(fil_api::get_staged_sectors(&self.sb)?)
+    }
+
+    fn read_piece(&self, key: &str) -> Result<Vec<u8>, Error> {
+        Ok(fil_api::read_piece_from_sealed_sector(&self.sb, key)?)
+    }
+
+    fn generate_post(
+        &self,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: &[u8; 32],
+    ) -> Result<PoStOutput, Error> {
+        let out = fil_api::generate_post(&self.sb, comm_rs, challenge_seed)?;
+
+        Ok(PoStOutput {
+            proofs: out.proofs,
+            faults: out.faults,
+        })
+    }
+
+    fn verify_post(
+        &self,
+        sector_size: u64,
+        proof_partitions: u8,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: &[u8; 32],
+        proofs: Vec<Vec<u8>>,
+        faults: Vec<u64>,
+    ) -> Result<bool, Error> {
+        Ok(fil_api::verify_post(
+            sector_size,
+            proof_partitions,
+            comm_rs,
+            challenge_seed,
+            proofs,
+            faults,
+        )?)
+    }
+
+    fn verify_seal(
+        &self,
+        sector_size: u64,
+        comm_r: [u8; 32],
+        comm_d: [u8; 32],
+        comm_r_star: [u8; 32],
+        prover_id: &[u8; 31],
+        sector_id: &[u8; 31],
+        proof: Vec<u8>,
+    ) -> Result<bool, Error> {
+        Ok(fil_api::verify_seal(
+            sector_size,
+            comm_r,
+            comm_d,
+            comm_r_star,
+            prover_id,
+            sector_id,
+            proof,
+        )?)
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+use failure::{bail, format_err, Error};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// The version of the backup format, bumped on incompatible changes.
+pub const BACKUP_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const SETTINGS: &str = "settings.json";
+const METADATA: &str = "metadata";
+
+/// How often the metadata is read before giving up on a consistent snapshot.
+const SNAPSHOT_ATTEMPTS: usize = 10;
+
+/// Describes the content of a backup archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// When the backup was created, in seconds since the unix epoch.
+    pub created_at: u64,
+    pub prover_id: String,
+    pub sector_size: u64,
+    /// The sealed sectors the metadata refers to, used to validate a restore.
+    pub sealed_sectors: Vec<SealedSectorFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedSectorFile {
+    pub sector_id: u64,
+    pub path: String,
+    pub len: u64,
+}
+
+/// The files of the `metadata_dir`, by their path relative to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot(BTreeMap<PathBuf, Vec<u8>>);
+
+/// Reads all files of the `metadata_dir`.
+///
+/// The workers of the sector builder keep writing their metadata while sealing, so the files
+/// are read until two reads in a row agree.
+pub fn snapshot<P: AsRef<Path>>(metadata_dir: P) -> Result<Snapshot, Error> {
+    let mut last = read_files(metadata_dir.as_ref())?;
+    for _ in 0..SNAPSHOT_ATTEMPTS {
+        let current = read_files(metadata_dir.as_ref())?;
+        if current == last {
+            return Ok(current);
+        }
+        last = current;
+    }
+
+    bail!(
+        "the metadata in {} kept changing, try again once no sector is sealing",
+        metadata_dir.as_ref().display()
+    )
+}
+
+fn read_files(dir: &Path) -> Result<Snapshot, Error> {
+    fn walk(dir: &Path, rel: &Path, files: &mut BTreeMap<PathBuf, Vec<u8>>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let rel = rel.join(entry.file_name());
+            if path.is_dir() {
+                walk(&path, &rel, files)?;
+            } else if path.is_file() {
+                files.insert(rel, fs::read(&path)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut files = BTreeMap::new();
+    walk(dir, Path::new(""), &mut files)?;
+
+    Ok(Snapshot(files))
+}
+
+/// Writes a backup archive of the `metadata` snapshot and the settings to `output`.
+///
+/// The path is sent by a client, so an existing file is never overwritten.
+pub fn create<P: AsRef<Path>>(
+    manifest: &Manifest,
+    settings: &Settings,
+    metadata: &Snapshot,
+    output: P,
+) -> Result<(), Error> {
+    let output = output.as_ref();
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)
+        .map_err(|err| format_err!("can not create {}: {}", output.display(), err))?;
+    let mut archive = tar::Builder::new(file);
+
+    append(
+        &mut archive,
+        MANIFEST,
+        &serde_json::to_vec_pretty(manifest)?,
+    )?;
+    append(
+        &mut archive,
+        SETTINGS,
+        &serde_json::to_vec_pretty(settings)?,
+    )?;
+    for (rel, data) in &metadata.0 {
+        append(&mut archive, Path::new(METADATA).join(rel), data)?;
+    }
+    archive.into_inner()?.sync_all()?;
+
+    Ok(())
+}
+
+fn append<W: io::Write, P: AsRef<Path>>(
+    archive: &mut tar::Builder<W>,
+    path: P,
+    data: &[u8],
+) -> Result<(), Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(crate::store::now());
+    header.set_cksum();
+    archive.append_data(&mut header, path, data)?;
+
+    Ok(())
+}
+
+/// Restores a backup archive into `metadata_dir`, writing the backed up settings to
+/// `settings_path`.
+///
+/// Before anything is written, the archive is validated, and all sealed sectors it refers to
+/// must exist on disk with their recorded size. An existing, non empty `metadata_dir` is only
+/// replaced with `force`.
+pub fn restore<R: Read>(
+    archive: R,
+    metadata_dir: &Path,
+    settings_path: &Path,
+    force: bool,
+) -> Result<Manifest, Error> {
+    let mut manifest = None;
+    let mut settings = None;
+    let mut files = Vec::new();
+
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+
+        if path == Path::new(MANIFEST) {
+            manifest = Some(serde_json::from_slice::<Manifest>(&data)?);
+        } else if path == Path::new(SETTINGS) {
+            settings = Some(serde_json::from_slice::<Settings>(&data)?);
+        } else if let Ok(rel) = path.strip_prefix(METADATA) {
+            let is_normal = |c: Component| match c {
+                Component::Normal(_) => true,
+                _ => false,
+            };
+            if !rel.components().all(is_normal) {
+                bail!("invalid path in backup: {}", path.display());
+            }
+            files.push((rel.to_path_buf(), data));
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| format_err!("backup contains no {}", MANIFEST))?;
+    let settings = settings.ok_or_else(|| format_err!("backup contains no {}", SETTINGS))?;
+    if manifest.version != BACKUP_VERSION {
+        bail!(
+            "unsupported backup version {}, expected {}",
+            manifest.version,
+            BACKUP_VERSION
+        );
+    }
+
+    for sector in &manifest.sealed_sectors {
+        let len = fs::metadata(&sector.path)
+            .map_err(|err| {
+                format_err!(
+                    "sealed sector {} is missing: {}: {}",
+                    sector.sector_id,
+                    sector.path,
+                    err
+                )
+            })?
+            .len();
+        if len != sector.len {
+            bail!(
+                "sealed sector {} has {} bytes, expected {}: {}",
+                sector.sector_id,
+                len,
+                sector.len,
+                sector.path
+            );
+        }
+    }
+
+    let is_empty = match fs::read_dir(metadata_dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(_) => true,
+    };
+    if !is_empty {
+        if !force {
+            bail!(
+                "{} is not empty, use --force to replace it",
+                metadata_dir.display()
+            );
+        }
+        fs::remove_dir_all(metadata_dir)?;
+    }
+
+    for (rel, data) in files {
+        let path: PathBuf = metadata_dir.join(rel);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+    }
+    fs::write(settings_path, serde_json::to_vec_pretty(&settings)?)?;
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(sealed_sectors: Vec<SealedSectorFile>) -> Manifest {
+        Manifest {
+            version: BACKUP_VERSION,
+            created_at: 0,
+            prover_id: "01".into(),
+            sector_size: 1024,
+            sealed_sectors,
+        }
+    }
+
+    #[test]
+    fn create_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let metadata_dir = dir.path().join("meta");
+        fs::create_dir_all(metadata_dir.join("builder")).unwrap();
+        fs::write(metadata_dir.join("store.cbor"), b"store").unwrap();
+        fs::write(metadata_dir.join("builder").join("db"), b"sectors").unwrap();
+
+        let sealed = dir.path().join("sealed-1");
+        fs::write(&sealed, vec![1; 1024]).unwrap();
+        let manifest = manifest(vec![SealedSectorFile {
+            sector_id: 1,
+            path: sealed.to_string_lossy().into(),
+            len: 1024,
+        }]);
+        let settings = Settings {
+            metadata_dir: metadata_dir.to_string_lossy().into(),
+            ..Settings::default()
+        };
+
+        let snapshot = snapshot(&metadata_dir).unwrap();
+        let archive = dir.path().join("backup.tar");
+        create(&manifest, &settings, &snapshot, &archive).unwrap();
+        let created = fs::read(&archive).unwrap();
+        // Existing files are never overwritten.
+        assert!(create(&manifest, &settings, &snapshot, &archive).is_err());
+        assert_eq!(fs::read(&archive).unwrap(), created);
+
+        let restored_dir = dir.path().join("restored");
+        let settings_path = dir.path().join("settings.json");
+        let open = || File::open(&archive).unwrap();
+        let restored = restore(open(), &restored_dir, &settings_path, false).unwrap();
+        assert_eq!(restored.sealed_sectors.len(), 1);
+        assert_eq!(snapshot, read_files(&restored_dir).unwrap());
+        let restored_settings: Settings =
+            serde_json::from_slice(&fs::read(&settings_path).unwrap()).unwrap();
+        assert_eq!(restored_settings.metadata_dir, settings.metadata_dir);
+
+        // An existing metadata_dir is only replaced with force.
+        assert!(restore(open(), &restored_dir, &settings_path, false).is_err());
+        restore(open(), &restored_dir, &settings_path, true).unwrap();
+
+        // All sealed sectors must be present with their size.
+        fs::write(&sealed, vec![1; 10]).unwrap();
+        let other_dir = dir.path().join("other");
+        assert!(restore(open(), &other_dir, &settings_path, false).is_err());
+        assert!(!other_dir.exists());
+    }
+
+    #[test]
+    fn path_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("backup.tar");
+
+        // `tar::Builder` refuses to write such paths, so the header is filled in directly.
+        let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+        let data = b"evil";
+        let mut header = tar::Header::new_gnu();
+        let name = b"metadata/../evil";
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &data[..]).unwrap();
+        builder.into_inner().unwrap();
+
+        let metadata_dir = dir.path().join("meta");
+        let settings_path = dir.path().join("settings.json");
+        let err = restore(
+            File::open(&archive).unwrap(),
+            &metadata_dir,
+            &settings_path,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid path"));
+        assert!(!dir.path().join("evil").exists());
+        assert!(!metadata_dir.exists());
+    }
+}
@@ -1,6 +1,6 @@
 // based on https://github.com/vorner/tokio-serde-cbor
 
-use failure::Error;
+use failure::{Error, Fail};
 use std::default::Default;
 use std::io::{Read, Result as IoResult, Write};
 use std::marker::PhantomData;
@@ -32,6 +32,17 @@ impl<'a, R: Read> Read for Counted<'a, R> {
     }
 }
 
+/// A frame is larger than the decoder accepts.
+#[derive(Debug, Fail)]
+#[fail(
+    display = "frame of at least {} bytes exceeds the limit of {} bytes",
+    size, limit
+)]
+pub struct FrameTooLarge {
+    pub size: usize,
+    pub limit: usize,
+}
+
 /// CBOR based decoder.
 ///
 /// This decoder can be used with `Framed` to decode CBOR encoded frames. Anything
@@ -39,12 +50,31 @@ impl<'a, R: Read> Read for Counted<'a, R> {
 #[derive(Clone, Debug)]
 pub struct Decoder<Item> {
     _data: PhantomData<fn() -> Item>,
+    max_frame_size: Option<usize>,
 }
 
 impl<'de, Item: Deserialize<'de>> Decoder<Item> {
-    /// Creates a new decoder.
+    /// Creates a new decoder, without a limit on the frame size.
     pub fn new() -> Self {
-        Self { _data: PhantomData }
+        Self {
+            _data: PhantomData,
+            max_frame_size: None,
+        }
+    }
+    /// Turns the decoder into one which fails with `FrameTooLarge` once a frame, complete or
+    /// not, exceeds `max_frame_size` bytes.
+    pub fn max_frame_size(self, max_frame_size: usize) -> Self {
+        Self {
+            max_frame_size: Some(max_frame_size),
+            ..self
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), Error> {
+        match self.max_frame_size {
+            Some(limit) if size > limit => Err(FrameTooLarge { size, limit }.into()),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -76,11 +106,16 @@ impl<'de, Item: Deserialize<'de>> IoDecoder for Decoder<Item> {
         match result {
             // If we read the item, we also need to consume the corresponding bytes.
             Ok(item) => {
+                self.check_size(pos)?;
                 src.split_to(pos);
                 Ok(Some(item))
             }
-            // Sometimes the EOF is signalled as IO error
-            Err(ref error) if error.is_eof() => Ok(None),
+            // Sometimes the EOF is signalled as IO error. Incomplete frames are checked as well,
+            // so that they can not grow the buffer without bounds.
+            Err(ref error) if error.is_eof() => {
+                self.check_size(src.len())?;
+                Ok(None)
+            }
             // Any other error is simply passed through.
             Err(e) => Err(e.into()),
         }
@@ -206,6 +241,13 @@ impl<'de, Dec: Deserialize<'de>, Enc: Serialize> Codec<Dec, Enc> {
             enc: Encoder { sd, ..self.enc },
         }
     }
+    /// Limits the size of the decoded frames, see `Decoder::max_frame_size`.
+    pub fn max_frame_size(self, max_frame_size: usize) -> Self {
+        Self {
+            dec: self.dec.max_frame_size(max_frame_size),
+            enc: self.enc,
+        }
+    }
     /// Turns the internal encoder into one with configured packed encoding.
     ///
     /// If `packed` is true, it omits the field names from the encoded data. That makes it smaller,
@@ -308,6 +350,29 @@ mod tests {
         decode(decoder);
     }
 
+    /// Frames over the limit are rejected, whether they are complete or not.
+    #[test]
+    fn max_frame_size() {
+        let encoded = serde_cbor::to_vec(&test_data()).unwrap();
+
+        let mut decoder: Decoder<TestData> = Decoder::new().max_frame_size(encoded.len());
+        let mut all = BytesMut::from(&encoded[..]);
+        assert_eq!(test_data(), decoder.decode(&mut all).unwrap().unwrap());
+
+        let mut decoder: Decoder<TestData> = Decoder::new().max_frame_size(encoded.len() - 1);
+        let mut all = BytesMut::from(&encoded[..]);
+        let err = decoder.decode(&mut all).unwrap_err();
+        assert!(err.downcast_ref::<FrameTooLarge>().is_some());
+        assert_eq!(encoded.len(), all.len());
+
+        // A byte string announcing 1 GiB is rejected once the limit is buffered.
+        let mut decoder: Decoder<serde_cbor::Value> = Decoder::new().max_frame_size(16);
+        let mut all = BytesMut::from(&[0x5a, 0x40, 0, 0, 0][..]);
+        assert!(decoder.decode(&mut all).unwrap().is_none());
+        all.extend(&[0; 16]);
+        decoder.decode(&mut all).unwrap_err();
+    }
+
     /// Test encoding.
     fn encode<Enc: IoEncoder<Item = TestData, Error = Error>>(enc: Enc) {
         let mut encoder = enc;
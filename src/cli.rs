@@ -0,0 +1,603 @@
+//! Prints the results of the `FilbaseClient` requests.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use failure::{bail, Error};
+
+use filbase::api::*;
+use filbase::client::{PostProofs, SealedPost};
+use filbase::settings::{self, SETTINGS};
+use filbase::{backup, import, piece, ClientConfig, FilbaseClient};
+
+/// A client for the daemon configured in the settings.
+fn client() -> FilbaseClient {
+    FilbaseClient::new(ClientConfig::from_settings(&SETTINGS.read().unwrap()))
+}
+
+pub async fn status() -> Result<(), Error> {
+    let status = client().status().await?;
+
+    println!("Prover ID: {}", hex::encode(&status.prover_id[..]));
+    println!("Sector size: {}", status.sector_size);
+    println!("Max user bytes per sector: {}", status.max_user_bytes);
+
+    match status.sealing_policy {
+        Some(decision) => {
+            println!("Sealing policy");
+            println!("  at: {}", decision.at);
+            println!("  due: {:?}", decision.due);
+            println!("  sealing started: {}", decision.seal);
+            println!("  sealed together: {:?}", decision.started);
+            println!("  reason: {}", decision.reason);
+        }
+        None => println!("Sealing policy: no decision"),
+    }
+
+    println!("Faults");
+    for fault in &status.faults {
+        println!("  sector {}: {}", fault.sector_id, fault.reason);
+    }
+
+    println!("Disks");
+    for disk in &status.disks {
+        println!(
+            "  {}: {} of {} bytes available",
+            disk.path, disk.available, disk.total
+        );
+        match disk.level {
+            DiskLevel::Ok => {}
+            DiskLevel::Warning => println!("  WARNING: {} is running out of space", disk.path),
+            DiskLevel::Critical => println!("  CRITICAL: {} is almost full", disk.path),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn post_generate(comm_rs: Vec<[u8; 32]>, challenge_seed: [u8; 32]) -> Result<(), Error> {
+    let PostProofs { proofs, faults } = client().post_generate(comm_rs, challenge_seed).await?;
+
+    println!("Proofs");
+    for proof in &proofs {
+        println!("{}", hex::encode(proof));
+    }
+
+    println!("Faults");
+    println!("{:?}", faults);
+
+    Ok(())
+}
+
+pub async fn post_generate_sealed(
+    sectors: SectorFilter,
+    challenge_seed: [u8; 32],
+) -> Result<(), Error> {
+    let SealedPost {
+        sector_size,
+        proof_partitions,
+        sector_ids,
+        comm_rs,
+        challenge_seed,
+        proofs,
+        faults,
+        excluded,
+    } = client()
+        .post_generate_sealed(sectors, challenge_seed)
+        .await?;
+
+    let comm_rs: Vec<_> = comm_rs.iter().map(hex::encode).collect();
+    let proofs: Vec<_> = proofs.iter().map(hex::encode).collect();
+    let faults: Vec<_> = faults.iter().map(|f| f.to_string()).collect();
+
+    println!("Sector IDs");
+    println!("{:?}", sector_ids);
+
+    println!("Comm Rs");
+    for comm_r in &comm_rs {
+        println!("{}", comm_r);
+    }
+
+    println!("Challenge Seed");
+    println!("{}", hex::encode(challenge_seed));
+
+    println!("Proofs");
+    for proof in &proofs {
+        println!("{}", proof);
+    }
+
+    println!("Faults");
+    println!("{:?}", faults);
+
+    if !excluded.is_empty() {
+        println!("Excluded, as the integrity checks found them faulty");
+        println!("{:?}", excluded);
+    }
+
+    println!("Verify with");
+    print!(
+        "filbase post verify --sector-size {} --proof-partitions {} --comm-rs {} --challenge-seed {} --proofs {}",
+        sector_size,
+        proof_partitions,
+        comm_rs.join(","),
+        hex::encode(challenge_seed),
+        proofs.join(","),
+    );
+    if !faults.is_empty() {
+        print!(" --faults {}", faults.join(","));
+    }
+    println!();
+
+    Ok(())
+}
+
+pub async fn post_verify(
+    sector_size: u64,
+    proof_partitions: u8,
+    comm_rs: Vec<[u8; 32]>,
+    challenge_seed: [u8; 32],
+    proofs: Vec<Vec<u8>>,
+    faults: Vec<u64>,
+) -> Result<(), Error> {
+    let valid = client()
+        .post_verify(
+            sector_size,
+            proof_partitions,
+            comm_rs,
+            challenge_seed,
+            proofs,
+            faults,
+        )
+        .await?;
+
+    println!("{}", valid);
+
+    Ok(())
+}
+
+pub async fn seal_generate() -> Result<(), Error> {
+    client().seal_all_staged().await
+}
+
+pub async fn seal_verify(
+    sector_size: u64,
+    comm_r: [u8; 32],
+    comm_d: [u8; 32],
+    comm_r_star: [u8; 32],
+    prover_id: [u8; 31],
+    sector_id: [u8; 31],
+    proof: Vec<u8>,
+) -> Result<(), Error> {
+    let valid = client()
+        .seal_verify(
+            sector_size,
+            comm_r,
+            comm_d,
+            comm_r_star,
+            prover_id,
+            sector_id,
+            proof,
+        )
+        .await?;
+
+    println!("{}", valid);
+
+    Ok(())
+}
+
+pub async fn seal_verify_sector(sector_id: u64) -> Result<(), Error> {
+    match client().seal_verify_sector(sector_id).await? {
+        SealVerifyResult::Valid => println!("true"),
+        SealVerifyResult::Invalid { field, reason } => {
+            println!("false");
+            match field {
+                Some(field) => println!("{}: {}", field, reason),
+                None => println!("{}", reason),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn seal_status(sector_id: u64) -> Result<(), Error> {
+    let status = client().seal_status(sector_id).await?;
+    println!("{:?}", status);
+
+    Ok(())
+}
+
+pub async fn sector_size(size: u64) -> Result<(), Error> {
+    let size = client().sector_size(size).await?;
+    println!("{}", size);
+
+    Ok(())
+}
+
+pub async fn sector_list_sealed() -> Result<(), Error> {
+    for el in &client().sector_list_sealed().await? {
+        println!("{}\t{}\t{:?}", el.meta.sector_id, el.path, el.meta);
+    }
+
+    Ok(())
+}
+
+pub async fn sector_list_staged() -> Result<(), Error> {
+    for el in &client().sector_list_staged().await? {
+        println!("{:?}", el);
+    }
+
+    Ok(())
+}
+
+/// Checks the sealed sectors for faults, `output` receives the faulty sector ids.
+pub async fn sector_check(checksums: bool, output: Option<&str>) -> Result<(), Error> {
+    let faults = client().sector_check(checksums).await?;
+
+    for fault in &faults {
+        println!("{}\t{}", fault.sector_id, fault.reason);
+    }
+    println!("{} faulty sectors", faults.len());
+
+    if let Some(output) = output {
+        let ids: Vec<_> = faults.iter().map(|f| f.sector_id.to_string()).collect();
+        std::fs::write(output, ids.join(","))?;
+    }
+
+    Ok(())
+}
+
+pub async fn sector_move(what: SectorMove) -> Result<(), Error> {
+    let moved = client().sector_move(what).await?;
+
+    for m in &moved {
+        println!("{}\t{} -> {}", m.sector_id, m.from, m.to);
+    }
+    println!("{} sectors moved", moved.len());
+
+    Ok(())
+}
+
+pub async fn sector_export(sector_id: u64, path: String) -> Result<(), Error> {
+    client().sector_export(sector_id, path).await
+}
+
+pub async fn sector_usage(piece_size: Option<u64>) -> Result<(), Error> {
+    let usage = client().sector_usage(piece_size).await?;
+
+    println!("SECTOR\tSTATE\tUSED\tFREE\tPIECES\tAGE\tFITS");
+    for s in &usage.sectors {
+        println!(
+            "{}\t{:?}\t{}\t{}\t{}\t{}\t{}",
+            s.sector_id,
+            s.state,
+            s.used_bytes,
+            s.free_bytes,
+            s.num_pieces,
+            s.age
+                .map(|age| format!("{}s", age))
+                .unwrap_or_else(|| "-".into()),
+            s.fits.map(|f| f.to_string()).unwrap_or_else(|| "-".into()),
+        );
+    }
+
+    println!(
+        "{} of {} staged sectors, {} bytes each",
+        usage.sectors.len(),
+        usage.max_num_staged_sectors,
+        usage.max_user_bytes
+    );
+    println!(
+        "{} bytes used, {} bytes free",
+        usage.used_bytes, usage.free_bytes
+    );
+    if let (Some(size), Some(fits)) = (piece_size, usage.fits) {
+        println!("A piece of {} bytes fits: {}", size, fits);
+    }
+
+    Ok(())
+}
+
+/// Adds a piece. If `expected_comm_p` is given, it is checked against the commitment the daemon
+/// computed over the bytes it handed to the sector builder.
+pub async fn piece_add<S1: AsRef<str>, S2: AsRef<str>>(
+    key: S1,
+    amount: Option<u64>,
+    path: S2,
+    expected_comm_p: Option<[u8; 32]>,
+) -> Result<(), Error> {
+    let added = client()
+        .piece_add(key.as_ref(), amount, path.as_ref())
+        .await?;
+
+    println!("{}", added.sector_id);
+    println!("{}", hex::encode(added.comm_p));
+
+    if let Some(expected) = expected_comm_p {
+        if expected != added.comm_p {
+            bail!(
+                "comm_p mismatch: expected {}, daemon stored {}",
+                hex::encode(expected),
+                hex::encode(added.comm_p)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// The sector size of the daemon, for commands which need it to check pieces locally.
+pub async fn daemon_sector_size() -> Result<u64, Error> {
+    match client().status().await {
+        Ok(status) => Ok(status.sector_size),
+        Err(err) => bail!(
+            "could not get the sector size from the daemon, pass --sector-size: {}",
+            err
+        ),
+    }
+}
+
+/// Computes the commitment of a piece locally.
+pub fn piece_commit<S: AsRef<str>>(
+    path: S,
+    amount: Option<u64>,
+    sector_size: u64,
+) -> Result<(), Error> {
+    let amount = match amount {
+        Some(amount) => amount,
+        None => std::fs::metadata(path.as_ref())?.len(),
+    };
+    let commitment = piece::commitment(path.as_ref(), amount, sector_size)?;
+
+    println!("comm_p: {}", hex::encode(commitment.comm_p));
+    println!("bytes: {}", commitment.num_bytes);
+    println!("padded bytes: {}", commitment.padded_bytes);
+
+    Ok(())
+}
+
+pub async fn piece_read<S: AsRef<str>>(key: S) -> Result<(), Error> {
+    let bytes = client().piece_read(key.as_ref()).await?;
+    println!("{}", hex::encode(bytes));
+
+    Ok(())
+}
+
+pub async fn piece_list(
+    prefix: Option<String>,
+    offset: u64,
+    limit: u64,
+    include_removed: bool,
+) -> Result<(), Error> {
+    let list = client()
+        .piece_list(prefix, offset, limit, include_removed)
+        .await?;
+
+    println!("KEY\tBYTES\tSECTOR\tSTATE\tADDED");
+    for piece in &list.pieces {
+        print_piece(piece);
+    }
+    println!(
+        "showing {}-{} of {}",
+        offset.min(list.total),
+        offset + list.pieces.len() as u64,
+        list.total
+    );
+
+    Ok(())
+}
+
+pub async fn piece_info<S: AsRef<str>>(key: S) -> Result<(), Error> {
+    let piece = client().piece_info(key.as_ref()).await?;
+
+    println!("Key: {}", piece.key);
+    println!("Bytes: {}", piece.num_bytes);
+    println!("Sector: {}", piece.sector_id);
+    println!("State: {:?}", piece.sector_state);
+    match piece.added_at {
+        Some(added_at) => println!("Added: {}", added_at),
+        None => println!("Added: unknown"),
+    }
+    match piece.comm_p {
+        Some(comm_p) => println!("comm_p: {}", hex::encode(comm_p)),
+        None => println!("comm_p: unknown"),
+    }
+    if let Some(removed) = piece.removed {
+        println!("Removed: {:?}", removed);
+    }
+
+    Ok(())
+}
+
+/// Adds all pieces from a directory or manifest.
+///
+/// Keys the daemon already knows are skipped, so an interrupted import can simply be started again.
+pub async fn piece_import<P: AsRef<Path>>(source: P) -> Result<(), Error> {
+    let entries = import::read_entries(source)?;
+    let client = client();
+
+    let max_user_bytes = client.status().await?.max_user_bytes;
+    // Expired keys are left to the daemon, which explains why they can not be added again.
+    let known: HashSet<_> = client
+        .piece_list(None, 0, u64::max_value(), false)
+        .await?
+        .pieces
+        .into_iter()
+        .map(|p| p.key)
+        .collect();
+
+    let total = entries.len();
+    let (mut added, mut skipped) = (0, 0);
+    let mut failed = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let progress = format!("[{}/{}] {}", i + 1, total, entry.key);
+
+        if known.contains(&entry.key) {
+            println!("{}: already imported, skipping", progress);
+            skipped += 1;
+            continue;
+        }
+
+        let size = match entry.size {
+            Some(size) => Ok(size),
+            None => std::fs::metadata(&entry.path).map(|metadata| metadata.len()),
+        };
+        let size = match size {
+            Ok(size) => size,
+            Err(err) => {
+                let reason = format!("{}: {}", entry.path.display(), err);
+                println!("{}: failed: {}", progress, reason);
+                failed.push((entry.key, reason));
+                continue;
+            }
+        };
+        if size > max_user_bytes {
+            let reason = format!(
+                "{} bytes exceed the maximum of {} bytes per sector",
+                size, max_user_bytes
+            );
+            println!("{}: rejected, {}", progress, reason);
+            failed.push((entry.key, reason));
+            continue;
+        }
+
+        let res = client
+            .piece_add(entry.key.as_str(), Some(size), entry.path.to_string_lossy())
+            .await;
+        match res {
+            Ok(piece) => {
+                println!(
+                    "{}: added to sector {}, comm_p {}",
+                    progress,
+                    piece.sector_id,
+                    hex::encode(piece.comm_p)
+                );
+                added += 1;
+            }
+            Err(err) => {
+                println!("{}: failed: {}", progress, err);
+                failed.push((entry.key, err.to_string()));
+            }
+        }
+    }
+
+    println!(
+        "{} added, {} skipped, {} failed, of {}",
+        added,
+        skipped,
+        failed.len(),
+        total
+    );
+    if !failed.is_empty() {
+        println!("failed:");
+        for (key, reason) in &failed {
+            println!("  {}: {}", key, reason);
+        }
+        bail!("{} pieces could not be imported", failed.len());
+    }
+
+    Ok(())
+}
+
+pub async fn piece_expire<S: AsRef<str>>(key: S) -> Result<(), Error> {
+    client().piece_expire(key.as_ref()).await
+}
+
+pub async fn backup_create(output: String) -> Result<(), Error> {
+    client().backup_create(output.as_str()).await?;
+    println!("wrote the backup to {}", output);
+
+    Ok(())
+}
+
+/// Restores a backup locally, the daemon must not be running.
+pub fn backup_restore<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive: P,
+    settings_path: Q,
+    force: bool,
+) -> Result<(), Error> {
+    let metadata_dir = SETTINGS.read().unwrap().metadata_dir.clone();
+    let manifest = backup::restore(
+        std::fs::File::open(archive)?,
+        Path::new(&metadata_dir),
+        settings_path.as_ref(),
+        force,
+    )?;
+
+    println!(
+        "restored metadata of prover {} with {} sealed sectors into {}",
+        manifest.prover_id,
+        manifest.sealed_sectors.len(),
+        metadata_dir
+    );
+    println!(
+        "the backed up settings were written to {}, start the daemon with `--config {}` to use them",
+        settings_path.as_ref().display(),
+        settings_path.as_ref().display()
+    );
+
+    Ok(())
+}
+
+pub fn config_init<P: AsRef<Path>>(path: P, force: bool) -> Result<(), Error> {
+    let path = path.as_ref();
+    if path.exists() && !force {
+        bail!(
+            "{} already exists, use --force to replace it",
+            path.display()
+        );
+    }
+    std::fs::write(path, settings::template()?)?;
+
+    println!("wrote the default settings to {}", path.display());
+
+    Ok(())
+}
+
+pub fn config_show(path: Option<&str>, cli: &[&str]) -> Result<(), Error> {
+    let sources = SETTINGS.read().unwrap().sources(path, cli)?;
+    for (key, value, source) in sources {
+        println!("{} = {}\t# {}", key, value, source);
+    }
+
+    Ok(())
+}
+
+pub fn config_check(path: &str) -> Result<(), Error> {
+    let settings = settings::Settings::check(path)?;
+    settings.validate_dirs()?;
+
+    println!("{} is valid", path);
+
+    Ok(())
+}
+
+pub async fn config_reload() -> Result<(), Error> {
+    let reload = client().config_reload().await?;
+
+    if reload.applied.is_empty() {
+        println!("no changes applied");
+    }
+    for key in &reload.applied {
+        println!("applied: {}", key);
+    }
+    for key in &reload.ignored {
+        println!("ignored until restart: {}", key);
+    }
+
+    Ok(())
+}
+
+fn print_piece(piece: &PieceInfo) {
+    println!(
+        "{}\t{}\t{}\t{:?}\t{}",
+        piece.key,
+        piece.num_bytes,
+        piece.sector_id,
+        piece.sector_state,
+        piece
+            .added_at
+            .map(|t| t.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+}
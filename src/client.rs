@@ -1,194 +1,570 @@
-use failure::{bail, format_err, Error};
+//! A typed client for the filbase daemon.
+//!
+//! ```no_run
+//! # #![feature(async_await)]
+//! # async fn run() -> Result<(), failure::Error> {
+//! use filbase::{ClientConfig, FilbaseClient};
+//!
+//! let client = FilbaseClient::new(ClientConfig::new("127.0.0.1:9988"));
+//! let status = client.status().await?;
+//! println!("sector size: {}", status.sector_size);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use failure::{Error, Fail};
+use filecoin_proofs::api::sector_builder::metadata::{SealStatus, StagedSectorMetadata};
+use futures::future::{self, Either};
+use futures::pin_mut;
 use futures::prelude::*;
 use futures_codec::Framed;
+use futures_timer::Delay;
 use runtime::net::TcpStream;
 
 use crate::api::*;
 use crate::cbor_codec::Codec;
-use crate::settings::SETTINGS;
-
-pub async fn post_generate(comm_rs: Vec<[u8; 32]>, challenge_seed: [u8; 32]) -> Result<(), Error> {
-    let res = send(Request::PostGenerate {
-        comm_rs,
-        challenge_seed,
-    })
-    .await?;
-
-    match res {
-        Response::PostGenerate { proofs, faults } => {
-            println!("Proofs");
-            for proof in &proofs {
-                println!("{}", hex::encode(proof));
-            }
+use crate::settings::Settings;
 
-            println!("Faults");
-            println!("{:?}", faults);
-        }
-        _ => bail!("Invalid server response"),
-    }
-
-    Ok(())
-}
-
-pub async fn post_verify(
-    sector_size: u64,
-    proof_partitions: u8,
-    comm_rs: Vec<[u8; 32]>,
-    challenge_seed: [u8; 32],
-    proofs: Vec<Vec<u8>>,
-    faults: Vec<u64>,
-) -> Result<(), Error> {
-    let res = send(Request::PostVerify {
-        sector_size,
-        proof_partitions,
-        comm_rs,
-        challenge_seed,
-        proofs,
-        faults,
-    })
-    .await?;
-
-    match res {
-        Response::PostVerify(valid) => {
-            println!("{}", valid);
+/// Configuration of a `FilbaseClient`.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// The address of the daemon, as `host:port`.
+    pub endpoint: String,
+    /// How long to wait for a connection, `None` waits forever.
+    pub connect_timeout: Option<Duration>,
+    /// How long to wait for the response to a request, `None` waits forever. Sealing and PoSt
+    /// requests can take a long time.
+    pub request_timeout: Option<Duration>,
+    /// How long an unused connection is kept open for the next request.
+    pub idle_timeout: Duration,
+    /// How often a request is retried when the connection failed. Requests are always retried if
+    /// they were not written yet, but only idempotent ones if the daemon may have received them.
+    pub retries: u32,
+    /// The delay before the first retry, doubled for each further one.
+    pub retry_backoff: Duration,
+}
+
+impl ClientConfig {
+    pub fn new<S: Into<String>>(endpoint: S) -> Self {
+        ClientConfig {
+            endpoint: endpoint.into(),
+            connect_timeout: Some(Duration::from_secs(10)),
+            request_timeout: None,
+            idle_timeout: Duration::from_secs(60),
+            retries: 3,
+            retry_backoff: Duration::from_millis(200),
         }
-        _ => bail!("Invalid server response"),
     }
 
-    Ok(())
+    /// Connects to the daemon configured in `settings`.
+    pub fn from_settings(settings: &Settings) -> Self {
+        ClientConfig::new(settings.server())
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.retry_backoff = backoff;
+        self
+    }
 }
 
-pub async fn seal_generate() -> Result<(), Error> {
-    let res = send(Request::SealAllStaged).await?;
-    match res {
-        Response::SealAllStaged => {}
-        _ => bail!("Invalid server response"),
+impl Default for ClientConfig {
+    fn default() -> Self {
+        ClientConfig::from_settings(&Settings::default())
     }
+}
+
+/// Errors of a request, besides those of the transport and the `InsufficientSpace` rejection.
+#[derive(Debug, Fail)]
+pub enum ClientError {
+    #[fail(display = "could not connect within {:?}", _0)]
+    ConnectTimeout(Duration),
+    #[fail(display = "no response within {:?}", _0)]
+    Timeout(Duration),
+    #[fail(display = "the daemon closed the connection")]
+    ConnectionClosed,
+    #[fail(display = "Server error: {}", _0)]
+    Server(String),
+    #[fail(display = "Invalid server response: {}", _0)]
+    InvalidResponse(String),
+    #[fail(
+        display = "the daemon speaks protocol version {}, the client version {}",
+        daemon, client
+    )]
+    ProtocolVersion { daemon: u32, client: u32 },
+}
+
+/// A PoSt over a list of replica commitments.
+#[derive(Debug, Clone)]
+pub struct PostProofs {
+    pub proofs: Vec<Vec<u8>>,
+    pub faults: Vec<u64>,
+}
+
+/// A PoSt over the daemon's own sealed sectors, with all inputs needed to verify it.
+#[derive(Debug, Clone)]
+pub struct SealedPost {
+    pub sector_size: u64,
+    pub proof_partitions: u8,
+    pub sector_ids: Vec<u64>,
+    pub comm_rs: Vec<[u8; 32]>,
+    pub challenge_seed: [u8; 32],
+    pub proofs: Vec<Vec<u8>>,
+    pub faults: Vec<u64>,
+    /// Sectors left out of the proof, as the integrity checks found them faulty.
+    pub excluded: Vec<u64>,
+}
 
-    Ok(())
+/// Where a piece was added.
+#[derive(Debug, Clone)]
+pub struct AddedPiece {
+    pub sector_id: u64,
+    pub comm_p: [u8; 32],
 }
 
-pub async fn seal_verify(
-    sector_size: u64,
-    comm_r: [u8; 32],
-    comm_d: [u8; 32],
-    comm_r_star: [u8; 32],
-    prover_id: [u8; 31],
-    sector_id: [u8; 31],
-    proof: Vec<u8>,
-) -> Result<(), Error> {
-    let res = send(Request::SealVerify {
-        sector_size,
-        comm_r,
-        comm_d,
-        comm_r_star,
-        prover_id,
-        sector_id,
-        proof,
-    })
-    .await?;
+/// A page of pieces.
+#[derive(Debug, Clone)]
+pub struct PieceList {
+    /// The number of pieces matching the prefix, ignoring the offset and limit.
+    pub total: u64,
+    pub pieces: Vec<PieceInfo>,
+}
 
-    match res {
-        Response::SealVerify(valid) => {
-            println!("{}", valid);
+/// Maps the expected response variant to its data, or fails with `InvalidResponse`.
+macro_rules! expect {
+    ($response:expr, $pattern:pat => $value:expr) => {
+        match $response {
+            $pattern => Ok($value),
+            other => Err(ClientError::InvalidResponse(format!("{:?}", other)).into()),
         }
-        _ => bail!("Invalid server response"),
-    }
+    };
+}
 
-    Ok(())
+/// A client of the filbase daemon, with one method per `Request`.
+///
+/// The connection is kept open between requests, and shared by clones of the client. Concurrent
+/// requests open additional connections.
+#[derive(Clone, Default)]
+pub struct FilbaseClient {
+    config: ClientConfig,
+    idle: Arc<Mutex<Option<Connection>>>,
 }
 
-pub async fn seal_status(sector_id: u64) -> Result<(), Error> {
-    let res = send(Request::SealStatus(sector_id)).await?;
-    match res {
-        Response::SealStatus(status) => {
-            println!("{:?}", status);
+impl fmt::Debug for FilbaseClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FilbaseClient")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl FilbaseClient {
+    pub fn new(config: ClientConfig) -> Self {
+        FilbaseClient {
+            config,
+            idle: Default::default(),
         }
-        _ => bail!("Invalid server response"),
     }
 
-    Ok(())
-}
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    pub async fn status(&self) -> Result<Status, Error> {
+        expect!(self.send(Request::Status).await?, Response::Status(status) => status)
+    }
 
-pub async fn sector_size(size: u64) -> Result<(), Error> {
-    let response = send(Request::SectorSize(size)).await?;
+    pub async fn post_generate(
+        &self,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: [u8; 32],
+    ) -> Result<PostProofs, Error> {
+        let response = self
+            .send(Request::PostGenerate {
+                comm_rs,
+                challenge_seed,
+            })
+            .await?;
 
-    match response {
-        Response::SectorSize(size) => println!("{}", size),
-        _ => bail!("Invalid server response"),
+        expect!(response, Response::PostGenerate { proofs, faults } => PostProofs { proofs, faults })
     }
 
-    Ok(())
-}
+    pub async fn post_generate_sealed(
+        &self,
+        sectors: SectorFilter,
+        challenge_seed: [u8; 32],
+    ) -> Result<SealedPost, Error> {
+        let response = self
+            .send(Request::PostGenerateSealed {
+                sectors,
+                challenge_seed,
+            })
+            .await?;
 
-pub async fn sector_list_sealed() -> Result<(), Error> {
-    let response = send(Request::SectorListSealed).await?;
-    match response {
-        Response::SectorListSealed(list) => {
-            for el in &list {
-                println!("{:?}", el);
-            }
-        }
-        _ => bail!("Invalid server response"),
+        expect!(response, Response::PostGenerateSealed {
+            sector_size,
+            proof_partitions,
+            sector_ids,
+            comm_rs,
+            challenge_seed,
+            proofs,
+            faults,
+            excluded,
+        } => SealedPost {
+            sector_size,
+            proof_partitions,
+            sector_ids,
+            comm_rs,
+            challenge_seed,
+            proofs,
+            faults,
+            excluded,
+        })
     }
 
-    Ok(())
-}
-pub async fn sector_list_staged() -> Result<(), Error> {
-    let response = send(Request::SectorListStaged).await?;
-    match response {
-        Response::SectorListStaged(list) => {
-            for el in &list {
-                println!("{:?}", el);
+    pub async fn post_verify(
+        &self,
+        sector_size: u64,
+        proof_partitions: u8,
+        comm_rs: Vec<[u8; 32]>,
+        challenge_seed: [u8; 32],
+        proofs: Vec<Vec<u8>>,
+        faults: Vec<u64>,
+    ) -> Result<bool, Error> {
+        let response = self
+            .send(Request::PostVerify {
+                sector_size,
+                proof_partitions,
+                comm_rs,
+                challenge_seed,
+                proofs,
+                faults,
+            })
+            .await?;
+
+        expect!(response, Response::PostVerify(valid) => valid)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn seal_verify(
+        &self,
+        sector_size: u64,
+        comm_r: [u8; 32],
+        comm_d: [u8; 32],
+        comm_r_star: [u8; 32],
+        prover_id: [u8; 31],
+        sector_id: [u8; 31],
+        proof: Vec<u8>,
+    ) -> Result<bool, Error> {
+        let response = self
+            .send(Request::SealVerify {
+                sector_size,
+                comm_r,
+                comm_d,
+                comm_r_star,
+                prover_id,
+                sector_id,
+                proof,
+            })
+            .await?;
+
+        expect!(response, Response::SealVerify(valid) => valid)
+    }
+
+    pub async fn seal_verify_sector(&self, sector_id: u64) -> Result<SealVerifyResult, Error> {
+        let response = self.send(Request::SealVerifySector(sector_id)).await?;
+        expect!(response, Response::SealVerifySector(result) => result)
+    }
+
+    pub async fn seal_all_staged(&self) -> Result<(), Error> {
+        expect!(self.send(Request::SealAllStaged).await?, Response::SealAllStaged => ())
+    }
+
+    pub async fn seal_status(&self, sector_id: u64) -> Result<SealStatus, Error> {
+        let response = self.send(Request::SealStatus(sector_id)).await?;
+        expect!(response, Response::SealStatus(status) => status)
+    }
+
+    pub async fn sector_size(&self, size: u64) -> Result<u64, Error> {
+        let response = self.send(Request::SectorSize(size)).await?;
+        expect!(response, Response::SectorSize(size) => size)
+    }
+
+    pub async fn sector_list_sealed(&self) -> Result<Vec<SealedSector>, Error> {
+        let response = self.send(Request::SectorListSealed).await?;
+        expect!(response, Response::SectorListSealed(list) => list)
+    }
+
+    pub async fn sector_list_staged(&self) -> Result<Vec<StagedSectorMetadata>, Error> {
+        let response = self.send(Request::SectorListStaged).await?;
+        expect!(response, Response::SectorListStaged(list) => list)
+    }
+
+    pub async fn sector_usage(&self, piece_size: Option<u64>) -> Result<SectorUsage, Error> {
+        let response = self.send(Request::SectorUsage(piece_size)).await?;
+        expect!(response, Response::SectorUsage(usage) => usage)
+    }
+
+    pub async fn sector_check(&self, checksums: bool) -> Result<Vec<SectorFault>, Error> {
+        let response = self.send(Request::SectorCheck { checksums }).await?;
+        expect!(response, Response::SectorCheck(faults) => faults)
+    }
+
+    pub async fn sector_move(&self, what: SectorMove) -> Result<Vec<SectorMoved>, Error> {
+        let response = self.send(Request::SectorMove(what)).await?;
+        expect!(response, Response::SectorMove(moved) => moved)
+    }
+
+    /// Exports a sealed sector to `path`, on the host of the daemon.
+    pub async fn sector_export<S: Into<String>>(
+        &self,
+        sector_id: u64,
+        path: S,
+    ) -> Result<(), Error> {
+        let path = path.into();
+        let response = self.send(Request::SectorExport { sector_id, path }).await?;
+        expect!(response, Response::SectorExport => ())
+    }
+
+    /// Adds the file at `path`, on the host of the daemon, as a piece. Without an `amount`, the
+    /// whole file is added.
+    pub async fn piece_add<S1: Into<String>, S2: Into<String>>(
+        &self,
+        key: S1,
+        amount: Option<u64>,
+        path: S2,
+    ) -> Result<AddedPiece, Error> {
+        let response = self
+            .send(Request::PieceAdd {
+                key: key.into(),
+                amount,
+                path: path.into(),
+            })
+            .await?;
+
+        expect!(response, Response::PieceAdd { sector_id, comm_p } => AddedPiece { sector_id, comm_p })
+    }
+
+    pub async fn piece_read<S: Into<String>>(&self, key: S) -> Result<Vec<u8>, Error> {
+        let response = self.send(Request::PieceRead(key.into())).await?;
+        expect!(response, Response::PieceRead(bytes) => bytes)
+    }
+
+    pub async fn piece_list(
+        &self,
+        prefix: Option<String>,
+        offset: u64,
+        limit: u64,
+        include_removed: bool,
+    ) -> Result<PieceList, Error> {
+        let response = self
+            .send(Request::PieceList {
+                prefix,
+                offset,
+                limit,
+                include_removed,
+            })
+            .await?;
+
+        expect!(response, Response::PieceList { total, pieces } => PieceList { total, pieces })
+    }
+
+    pub async fn piece_info<S: Into<String>>(&self, key: S) -> Result<PieceInfo, Error> {
+        let response = self.send(Request::PieceInfo(key.into())).await?;
+        expect!(response, Response::PieceInfo(piece) => piece)
+    }
+
+    pub async fn piece_expire<S: Into<String>>(&self, key: S) -> Result<(), Error> {
+        let response = self.send(Request::PieceExpire(key.into())).await?;
+        expect!(response, Response::PieceExpire => ())
+    }
+
+    /// Writes the backup archive to `path`, on the host of the daemon.
+    pub async fn backup_create<S: Into<String>>(&self, path: S) -> Result<(), Error> {
+        let path = path.into();
+        let response = self.send(Request::BackupCreate { path }).await?;
+        expect!(response, Response::BackupCreate => ())
+    }
+
+    pub async fn config_reload(&self) -> Result<ConfigReload, Error> {
+        let response = self.send(Request::ConfigReload).await?;
+        expect!(response, Response::ConfigReload(reload) => reload)
+    }
+
+    /// Sends a request and returns the response, mapping the error responses to errors.
+    ///
+    /// Requests are retried with backoff when the connection fails, like after a restart of the
+    /// daemon. Any request is retried if it was not written yet, but only idempotent ones once the
+    /// daemon may have received them. Timeouts and protocol version mismatches are never retried.
+    pub async fn send(&self, request: Request) -> Result<Response, Error> {
+        let mut backoff = self.config.retry_backoff;
+
+        let mut retries = 0;
+        let response = loop {
+            let err = match self.try_send(request.clone()).await {
+                Ok(response) => break response,
+                Err(Failed::NotSent(err)) => err,
+                Err(Failed::Sent(err)) if request.is_idempotent() => err,
+                Err(Failed::Sent(err)) => return Err(err),
+            };
+            if retries >= self.config.retries || !is_transient(&err) {
+                return Err(err);
             }
+
+            Delay::new(backoff).await?;
+            backoff *= 2;
+            retries += 1;
+        };
+
+        match response {
+            Response::InsufficientSpace(err) => Err(err.into()),
+            Response::Err(err) => Err(ClientError::Server(err).into()),
+            _ => Ok(response),
         }
-        _ => bail!("Invalid server response"),
     }
 
-    Ok(())
-}
+    /// Sends a request over the idle connection or a new one. The connection is kept for the next
+    /// request, unless it failed.
+    async fn try_send(&self, request: Request) -> Result<Response, Failed> {
+        let mut connection = match self.take_idle() {
+            Some(connection) => connection,
+            None => self.connect().await.map_err(Failed::NotSent)?,
+        };
 
-pub async fn piece_add<S1: AsRef<str>, S2: AsRef<str>>(
-    key: S1,
-    amount: Option<u64>,
-    path: S2,
-) -> Result<(), Error> {
-    let res = send(Request::PieceAdd {
-        key: key.as_ref().into(),
-        amount,
-        path: path.as_ref().into(),
-    })
-    .await?;
+        // Once writing started, the daemon may have received the request.
+        let response = with_timeout(
+            self.config.request_timeout,
+            ClientError::Timeout,
+            connection.exchange(request),
+        )
+        .await
+        .map_err(Failed::Sent)?;
+
+        connection.last_used = Instant::now();
+        self.idle.lock().unwrap().get_or_insert(connection);
+
+        Ok(response)
+    }
 
-    match res {
-        Response::PieceAdd(id) => println!("{}", id),
-        _ => bail!("Invalid server response"),
+    fn take_idle(&self) -> Option<Connection> {
+        self.idle
+            .lock()
+            .unwrap()
+            .take()
+            .filter(|connection| connection.last_used.elapsed() < self.config.idle_timeout)
     }
 
-    Ok(())
+    /// Connects to the daemon, and exchanges the protocol version.
+    async fn connect(&self) -> Result<Connection, Error> {
+        let endpoint = self.config.endpoint.clone();
+        let stream = with_timeout(
+            self.config.connect_timeout,
+            ClientError::ConnectTimeout,
+            async { TcpStream::connect(endpoint).await.map_err(Error::from) },
+        )
+        .await?;
+
+        let mut connection = Connection {
+            framed: Framed::new(stream, Codec::new()),
+            last_used: Instant::now(),
+        };
+
+        // Daemons before protocol version 2 close the connection on the handshake.
+        let handshake = Request::Handshake {
+            protocol_version: PROTOCOL_VERSION,
+        };
+        let response = with_timeout(
+            self.config.connect_timeout,
+            ClientError::ConnectTimeout,
+            connection.exchange(handshake),
+        )
+        .await?;
+        match response {
+            Response::Handshake { protocol_version } if protocol_version == PROTOCOL_VERSION => {
+                Ok(connection)
+            }
+            Response::Handshake { protocol_version } => Err(ClientError::ProtocolVersion {
+                daemon: protocol_version,
+                client: PROTOCOL_VERSION,
+            }
+            .into()),
+            Response::Err(err) => Err(ClientError::Server(err).into()),
+            other => Err(ClientError::InvalidResponse(format!("{:?}", other)).into()),
+        }
+    }
 }
 
-pub async fn piece_read<S: AsRef<str>>(key: S) -> Result<(), Error> {
-    let response = send(Request::PieceRead(key.as_ref().into())).await?;
+/// A failed attempt to send a request.
+enum Failed {
+    /// The request was not written to a connection.
+    NotSent(Error),
+    /// The daemon may have received the request.
+    Sent(Error),
+}
 
-    match response {
-        Response::PieceRead(bytes) => println!("{}", hex::encode(bytes)),
-        _ => bail!("Invalid server response"),
+/// Whether a failed connection may work when tried again, as when it was closed by a restarting
+/// daemon. Timeouts, protocol version mismatches and error responses to the handshake do not.
+fn is_transient(err: &Error) -> bool {
+    match err.downcast_ref::<ClientError>() {
+        Some(ClientError::ConnectionClosed) => true,
+        Some(_) => false,
+        None => err.downcast_ref::<io::Error>().is_some(),
     }
+}
 
-    Ok(())
+struct Connection {
+    framed: Framed<TcpStream, Codec<Response, Request>>,
+    last_used: Instant,
 }
 
-async fn send<'a>(msg: Request) -> Result<Response, Error> {
-    let server = SETTINGS.clone().read().unwrap().server();
-    let stream = TcpStream::connect(server).await?;
-    let mut framed = Framed::new(stream, Codec::new());
+impl Connection {
+    async fn exchange(&mut self, request: Request) -> Result<Response, Error> {
+        self.framed.send(request).await?;
+
+        match self.framed.next().await {
+            Some(response) => response,
+            None => Err(ClientError::ConnectionClosed.into()),
+        }
+    }
+}
 
-    framed.send(msg).await?;
+/// Fails with the error built by `on_timeout`, if `future` does not complete within `timeout`.
+async fn with_timeout<F, T>(
+    timeout: Option<Duration>,
+    on_timeout: fn(Duration) -> ClientError,
+    future: F,
+) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return future.await,
+    };
 
-    let response = framed.next().await.unwrap()?;
-    match response {
-        Response::Err(err) => Err(format_err!("Server error: {}", err)),
-        _ => Ok(response),
+    pin_mut!(future);
+    match future::select(future, Delay::new(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(on_timeout(timeout).into()),
     }
 }
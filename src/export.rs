@@ -0,0 +1,98 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+
+use failure::{format_err, Error};
+use filecoin_proofs::api::sector_builder::metadata::SealedSectorMetadata;
+use serde::{Deserialize, Serialize};
+
+use crate::integrity;
+
+/// The version of the sector archive format, bumped on incompatible changes.
+pub const EXPORT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const METADATA: &str = "metadata.cbor";
+const PROOF: &str = "proof";
+const REPLICA: &str = "replica";
+
+/// Describes an exported sealed sector.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub prover_id: String,
+    pub sector_id: u64,
+    pub sector_size: u64,
+    pub porep_partitions: u8,
+    /// The blake2b checksums of the other files in the archive, hex encoded.
+    pub metadata_checksum: String,
+    pub proof_checksum: String,
+    pub replica_checksum: String,
+}
+
+/// Writes the sealed replica, its metadata and proof into a tar archive at `output`. The path is
+/// sent by a client, so an existing file is never overwritten.
+///
+/// The archive is meant for keeping a sealed sector outside of the daemon. Importing it into
+/// another daemon is not implemented: the sector builder has no way to adopt a sealed sector,
+/// so that daemon could neither prove it nor read pieces from it. Nothing reads the archive yet.
+pub fn export<P: AsRef<Path>>(
+    meta: &SealedSectorMetadata,
+    prover_id: &[u8; 31],
+    sector_size: u64,
+    porep_partitions: u8,
+    output: P,
+) -> Result<(), Error> {
+    let metadata = serde_cbor::to_vec(meta)?;
+    let manifest = Manifest {
+        version: EXPORT_VERSION,
+        prover_id: hex::encode(&prover_id[..]),
+        sector_id: meta.sector_id,
+        sector_size,
+        porep_partitions,
+        metadata_checksum: hex::encode(checksum(&metadata[..])?),
+        proof_checksum: hex::encode(checksum(&meta.proof[..])?),
+        replica_checksum: hex::encode(integrity::blake2b_checksum(&meta.sector_access)?),
+    };
+
+    let output = output.as_ref();
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)
+        .map_err(|err| format_err!("can not create {}: {}", output.display(), err))?;
+    let mut archive = tar::Builder::new(file);
+    append(
+        &mut archive,
+        MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    append(&mut archive, METADATA, &metadata)?;
+    append(&mut archive, PROOF, &meta.proof)?;
+    archive.append_file(REPLICA, &mut File::open(&meta.sector_access)?)?;
+    archive.into_inner()?.sync_all()?;
+
+    Ok(())
+}
+
+fn append<W: io::Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    data: &[u8],
+) -> Result<(), Error> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(crate::store::now());
+    header.set_cksum();
+    archive.append_data(&mut header, path, data)?;
+
+    Ok(())
+}
+
+fn checksum<R: Read>(mut data: R) -> Result<Vec<u8>, Error> {
+    let mut state = blake2b_simd::State::new();
+    io::copy(&mut data, &mut state)?;
+
+    Ok(state.finalize().as_bytes().to_vec())
+}
@@ -0,0 +1,108 @@
+//! The bodies of the fuzz targets in `fuzz/`, shared with `tests/fuzz.rs`, which replays the
+//! inputs in `fuzz/regressions/`.
+
+use std::path::Path;
+
+use bytes::BytesMut;
+use futures_codec::Decoder as _;
+
+use crate::api::{Request, SectorMove};
+use crate::cbor_codec::{Codec, Decoder};
+use crate::testing::TestServer;
+
+/// Small enough to be hit by the fuzzer's inputs.
+const MAX_FRAME_SIZE: usize = 1024;
+
+/// Decodes requests the way a connection does. The first byte is the size of the chunks in which
+/// the rest arrives, so that partial frames are covered.
+pub fn decode(data: &[u8]) {
+    let (chunk_size, data) = match data.split_first() {
+        Some((chunk_size, data)) => (usize::from(*chunk_size).max(1), data),
+        None => return,
+    };
+
+    let mut codec: Codec<Request, ()> = Codec::new().max_frame_size(MAX_FRAME_SIZE);
+    let mut buf = BytesMut::new();
+    for chunk in data.chunks(chunk_size) {
+        buf.extend_from_slice(chunk);
+        loop {
+            let len = buf.len();
+            match codec.decode(&mut buf) {
+                Ok(Some(_)) => assert!(buf.len() < len, "decoded a frame without consuming it"),
+                Ok(None) => {
+                    assert_eq!(buf.len(), len, "consumed an incomplete frame");
+                    assert!(len <= MAX_FRAME_SIZE, "buffered past the frame limit");
+                    break;
+                }
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+/// Deserializes a request, which must survive a round trip through the encoding.
+pub fn request(data: &[u8]) {
+    let request = match serde_cbor::from_slice::<Request>(data) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+
+    let encoded = serde_cbor::to_vec(&request).expect("failed to encode a decoded request");
+    let mut decoder: Decoder<Request> = Decoder::new();
+    let decoded = decoder
+        .decode(&mut BytesMut::from(encoded))
+        .expect("failed to decode an encoded request")
+        .expect("encoded request is incomplete");
+    assert_eq!(format!("{:?}", request), format!("{:?}", decoded));
+}
+
+/// Handles a request on the fake backend. Paths are moved into the directory of the `server`,
+/// and config reloads are skipped, as they change the global settings.
+pub fn respond(server: &TestServer, data: &[u8]) {
+    let request = match serde_cbor::from_slice::<Request>(data) {
+        Ok(request) => request,
+        Err(_) => return,
+    };
+
+    if let Some(request) = confine(request, server.dir()) {
+        server.handle(request);
+    }
+}
+
+fn confine(request: Request, dir: &Path) -> Option<Request> {
+    let confine_path = |path: String| {
+        let name = Path::new(&path)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "file".into());
+        dir.join(name).to_string_lossy().into_owned()
+    };
+
+    let request = match request {
+        Request::PieceAdd { key, amount, path } => Request::PieceAdd {
+            key,
+            amount,
+            path: confine_path(path),
+        },
+        Request::SectorExport { sector_id, path } => Request::SectorExport {
+            sector_id,
+            path: confine_path(path),
+        },
+        Request::BackupCreate { path } => Request::BackupCreate {
+            path: confine_path(path),
+        },
+        Request::SectorMove(SectorMove::Sector { sector_id, to }) => {
+            Request::SectorMove(SectorMove::Sector {
+                sector_id,
+                to: confine_path(to),
+            })
+        }
+        Request::SectorMove(SectorMove::Drain { from }) => Request::SectorMove(SectorMove::Drain {
+            from: confine_path(from),
+        }),
+        Request::ConfigReload => return None,
+        request => request,
+    };
+
+    Some(request)
+}
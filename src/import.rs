@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use failure::{bail, format_err, Error};
+use serde::Deserialize;
+
+/// A single piece to import.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Entry {
+    pub key: String,
+    pub path: PathBuf,
+    pub size: Option<u64>,
+}
+
+/// Reads the entries to import from a directory, or from a CSV or JSON manifest.
+///
+/// For directories, every regular file is imported using its file name as key. Relative paths in
+/// manifests are resolved relative to the manifest.
+pub fn read_entries<P: AsRef<Path>>(source: P) -> Result<Vec<Entry>, Error> {
+    let source = source.as_ref();
+
+    let mut entries = if source.is_dir() {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(source)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let key = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| format_err!("invalid file name: {}", path.display()))?
+                .to_string();
+            entries.push(Entry {
+                key,
+                path,
+                size: None,
+            });
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        entries
+    } else {
+        let content = fs::read_to_string(source)?;
+        match source.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&content)?,
+            Some("csv") => parse_csv(&content)?,
+            _ => bail!(
+                "{} is neither a directory, nor a .csv or .json manifest",
+                source.display()
+            ),
+        }
+    };
+
+    let base = if source.is_dir() {
+        source
+    } else {
+        source.parent().unwrap_or_else(|| Path::new("."))
+    };
+    let cwd = std::env::current_dir()?;
+    for entry in &mut entries {
+        // The daemon might run in a different working directory. Missing files are left to fail
+        // on their own, without stopping the other entries.
+        let path = cwd.join(base).join(&entry.path);
+        entry.path = fs::canonicalize(&path).unwrap_or(path);
+    }
+
+    Ok(entries)
+}
+
+/// Parses lines of `key,path[,size]`, with an optional header line.
+fn parse_csv(content: &str) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.starts_with("key,")) {
+            continue;
+        }
+
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let size = match fields.len() {
+            2 => None,
+            3 if fields[2].is_empty() => None,
+            3 => Some(
+                fields[2]
+                    .parse()
+                    .map_err(|err| format_err!("line {}: invalid size: {}", i + 1, err))?,
+            ),
+            _ => bail!("line {}: expected key,path[,size]", i + 1),
+        };
+
+        entries.push(Entry {
+            key: fields[0].into(),
+            path: fields[1].into(),
+            size,
+        });
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        let entries = parse_csv("key,path,size\na, a.bin\nb,b.bin,\n\nc,c.bin,12\n").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    key: "a".into(),
+                    path: "a.bin".into(),
+                    size: None
+                },
+                Entry {
+                    key: "b".into(),
+                    path: "b.bin".into(),
+                    size: None
+                },
+                Entry {
+                    key: "c".into(),
+                    path: "c.bin".into(),
+                    size: Some(12)
+                },
+            ]
+        );
+
+        assert!(parse_csv("a").is_err());
+        assert!(parse_csv("a,a.bin,big").is_err());
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.bin"), b"a").unwrap();
+        let manifest = dir.path().join("pieces.csv");
+        fs::write(&manifest, "a,a.bin\nb,missing.bin\n").unwrap();
+
+        let entries = read_entries(&manifest).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.path.is_absolute()));
+        assert!(entries[0].path.exists());
+        assert!(!entries[1].path.exists());
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use failure::{format_err, Error};
+
+use crate::api::InsufficientSpace;
+use crate::settings::{Inbox, SETTINGS};
+
+/// The extension of sidecar files, holding the key of the piece with the same name.
+const SIDECAR_EXTENSION: &str = "key";
+
+/// The extension of the files explaining why adding a piece failed.
+const ERROR_EXTENSION: &str = "error";
+
+/// Watches the configured inbox directories and adds every complete file as a piece.
+///
+/// A file is considered complete once its size and modification time did not change between
+/// two polls. Hidden files and files ending in `.part` or `.tmp` are ignored, as are `.key` files
+/// if the keys are read from sidecars. Processed files are moved to the `done_dir` or
+/// `failed_dir` of their inbox, failures are explained in an additional `.error` file. Files
+/// which failed for a transient reason, like a lack of space, stay in the inbox and are tried
+/// again on the next poll. Files never overwrite earlier ones with the same name, they get a
+/// numbered suffix instead.
+pub fn watch<F>(add_piece: F)
+where
+    F: Fn(&str, &Path) -> Result<(), Error>,
+{
+    // The size and modification time of the files of each inbox, at the last poll.
+    let mut seen: HashMap<String, HashMap<PathBuf, (u64, SystemTime)>> = HashMap::new();
+
+    loop {
+        let (inboxes, interval) = {
+            let cfg = SETTINGS.read().unwrap();
+            (cfg.inboxes.clone(), cfg.inbox_interval)
+        };
+        thread::sleep(Duration::from_secs(interval.max(1)));
+
+        seen.retain(|dir, _| inboxes.iter().any(|inbox| inbox.dir == *dir));
+        for inbox in &inboxes {
+            let seen = seen.entry(inbox.dir.clone()).or_default();
+            if let Err(err) = poll(inbox, seen, &add_piece) {
+                println!("inbox {}: {}", inbox.dir, err);
+            }
+        }
+    }
+}
+
+fn poll<F>(
+    inbox: &Inbox,
+    seen: &mut HashMap<PathBuf, (u64, SystemTime)>,
+    add_piece: &F,
+) -> Result<(), Error>
+where
+    F: Fn(&str, &Path) -> Result<(), Error>,
+{
+    let mut present = HashSet::new();
+    for entry in fs::read_dir(&inbox.dir)? {
+        let path = entry?.path();
+        if !is_candidate(inbox, &path) {
+            continue;
+        }
+        present.insert(path.clone());
+
+        let meta = fs::metadata(&path)?;
+        let current = (meta.len(), meta.modified()?);
+        if seen.insert(path.clone(), current) != Some(current) {
+            // Still being written, or seen for the first time.
+            continue;
+        }
+
+        let key = match piece_key(inbox, &path)? {
+            Some(key) => key,
+            // Waiting for the sidecar.
+            None => continue,
+        };
+
+        match add_piece(&key, &path) {
+            Ok(()) => {
+                seen.remove(&path);
+                println!("inbox {}: added {} as {}", inbox.dir, path.display(), key);
+                move_to(inbox, &path, &inbox.done_dir)?;
+            }
+            Err(ref err) if is_transient(err) => {
+                // Still known as complete, so it is tried again on the next poll.
+                println!(
+                    "inbox {}: failed to add {}, will retry: {}",
+                    inbox.dir,
+                    path.display(),
+                    err
+                );
+            }
+            Err(err) => {
+                seen.remove(&path);
+                println!(
+                    "inbox {}: failed to add {}: {}",
+                    inbox.dir,
+                    path.display(),
+                    err
+                );
+                let moved = move_to(inbox, &path, &inbox.failed_dir)?;
+                let error = with_extension(&moved, ERROR_EXTENSION);
+                fs::write(error, format!("{}\n", err))?;
+            }
+        }
+    }
+
+    // Forget files that were removed before they were complete.
+    seen.retain(|path, _| present.contains(path));
+
+    Ok(())
+}
+
+fn is_candidate(inbox: &Inbox, path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    let is_sidecar = inbox.key_from_sidecar
+        && path.extension().and_then(|ext| ext.to_str()) == Some(SIDECAR_EXTENSION);
+
+    path.is_file()
+        && !name.starts_with('.')
+        && !name.ends_with(".part")
+        && !name.ends_with(".tmp")
+        && !is_sidecar
+}
+
+/// Errors which may go away on their own, like a full disk or a timeout.
+fn is_transient(err: &Error) -> bool {
+    if err.downcast_ref::<InsufficientSpace>().is_some() {
+        return true;
+    }
+
+    match err.downcast_ref::<io::Error>().map(io::Error::kind) {
+        Some(io::ErrorKind::TimedOut)
+        | Some(io::ErrorKind::Interrupted)
+        | Some(io::ErrorKind::WouldBlock) => true,
+        _ => false,
+    }
+}
+
+/// The key is either read from the sidecar file, or is the file name.
+fn piece_key(inbox: &Inbox, path: &Path) -> Result<Option<String>, Error> {
+    if inbox.key_from_sidecar {
+        let sidecar = sidecar_path(path);
+        if !sidecar.exists() {
+            return Ok(None);
+        }
+        let key = fs::read_to_string(&sidecar)?.trim().to_string();
+        if key.is_empty() {
+            return Err(format_err!("empty key in {}", sidecar.display()));
+        }
+
+        return Ok(Some(key));
+    }
+
+    Ok(path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(Into::into))
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    with_extension(path, SIDECAR_EXTENSION)
+}
+
+/// Appends `extension` to the file name, keeping any existing extension.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+/// Moves the file, and its sidecar if any, into `dir`, returning the new path of the file.
+///
+/// If `dir` already holds a file of that name, a numbered suffix is appended to the name.
+fn move_to(inbox: &Inbox, path: &Path, dir: &str) -> Result<PathBuf, Error> {
+    fs::create_dir_all(dir)?;
+    let name = path.file_name().unwrap_or_default();
+    let mut target = Path::new(dir).join(name);
+    let mut n = 0;
+    while target.exists()
+        || sidecar_path(&target).exists()
+        || with_extension(&target, ERROR_EXTENSION).exists()
+    {
+        n += 1;
+        let mut numbered = name.to_os_string();
+        numbered.push(format!(".{}", n));
+        target = Path::new(dir).join(numbered);
+    }
+
+    move_file(path, &target)?;
+    let sidecar = sidecar_path(path);
+    if inbox.key_from_sidecar && sidecar.exists() {
+        move_file(&sidecar, &sidecar_path(&target))?;
+    }
+
+    Ok(target)
+}
+
+/// Renames the file, or copies and removes it when renaming fails, as it does across
+/// filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Err(err) = fs::rename(from, to) {
+        if fs::copy(from, to).is_err() {
+            let _ = fs::remove_file(to);
+            return Err(err);
+        }
+        fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use failure::bail;
+
+    fn inbox(dir: &Path, key_from_sidecar: bool) -> Inbox {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let inbox = Inbox {
+            dir: path("inbox"),
+            done_dir: path("done"),
+            failed_dir: path("failed"),
+            key_from_sidecar,
+        };
+        fs::create_dir_all(&inbox.dir).unwrap();
+        inbox
+    }
+
+    #[test]
+    fn adds_complete_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), false);
+        fs::write(Path::new(&inbox.dir).join("a"), b"piece").unwrap();
+        fs::write(Path::new(&inbox.dir).join("b.part"), b"partial").unwrap();
+
+        let added = RefCell::new(Vec::new());
+        let add_piece = |key: &str, path: &Path| -> Result<(), Error> {
+            added.borrow_mut().push((key.to_string(), fs::read(path)?));
+            Ok(())
+        };
+        let mut seen = HashMap::new();
+
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert!(added.borrow().is_empty());
+
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert_eq!(*added.borrow(), vec![("a".to_string(), b"piece".to_vec())]);
+        assert!(Path::new(&inbox.done_dir).join("a").exists());
+        assert!(Path::new(&inbox.dir).join("b.part").exists());
+        assert!(seen.is_empty());
+    }
+
+    #[test]
+    fn failed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), false);
+        fs::write(Path::new(&inbox.dir).join("a"), b"piece").unwrap();
+
+        let add_piece = |_: &str, _: &Path| -> Result<(), Error> { bail!("no space") };
+        let mut seen = HashMap::new();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+
+        let failed = Path::new(&inbox.failed_dir);
+        assert!(failed.join("a").exists());
+        assert_eq!(
+            fs::read_to_string(failed.join("a.error")).unwrap(),
+            "no space\n"
+        );
+    }
+
+    #[test]
+    fn transient_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), false);
+        let path = Path::new(&inbox.dir).join("a");
+        fs::write(&path, b"piece").unwrap();
+
+        let full = RefCell::new(true);
+        let add_piece = |_: &str, _: &Path| -> Result<(), Error> {
+            if *full.borrow() {
+                return Err(InsufficientSpace {
+                    path: "staged".into(),
+                    required: 10,
+                    available: 0,
+                }
+                .into());
+            }
+            Ok(())
+        };
+        let mut seen = HashMap::new();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert!(path.exists());
+        assert!(!Path::new(&inbox.failed_dir).join("a").exists());
+
+        *full.borrow_mut() = false;
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert!(!path.exists());
+        assert!(Path::new(&inbox.done_dir).join("a").exists());
+    }
+
+    #[test]
+    fn key_files_without_sidecars() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), false);
+        fs::write(Path::new(&inbox.dir).join("a.key"), b"piece").unwrap();
+
+        let added = RefCell::new(Vec::new());
+        let add_piece = |key: &str, _: &Path| -> Result<(), Error> {
+            added.borrow_mut().push(key.to_string());
+            Ok(())
+        };
+        let mut seen = HashMap::new();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert_eq!(*added.borrow(), vec!["a.key".to_string()]);
+    }
+
+    #[test]
+    fn sidecar_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), true);
+        let path = Path::new(&inbox.dir).join("a");
+        fs::write(&path, b"piece").unwrap();
+
+        let added = RefCell::new(Vec::new());
+        let add_piece = |key: &str, _: &Path| -> Result<(), Error> {
+            added.borrow_mut().push(key.to_string());
+            Ok(())
+        };
+        let mut seen = HashMap::new();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert!(added.borrow().is_empty());
+
+        fs::write(sidecar_path(&path), "key\n").unwrap();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert_eq!(*added.borrow(), vec!["key".to_string()]);
+        assert!(Path::new(&inbox.done_dir).join("a.key").exists());
+        assert!(!sidecar_path(&path).exists());
+    }
+
+    #[test]
+    fn does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), true);
+        let done = Path::new(&inbox.done_dir);
+        fs::create_dir_all(done).unwrap();
+        fs::write(done.join("a"), b"first").unwrap();
+        fs::write(done.join("a.1.key"), b"key").unwrap();
+
+        let path = Path::new(&inbox.dir).join("a");
+        fs::write(&path, b"second").unwrap();
+        fs::write(sidecar_path(&path), b"key").unwrap();
+
+        let moved = move_to(&inbox, &path, &inbox.done_dir).unwrap();
+        assert_eq!(moved, done.join("a.2"));
+        assert_eq!(fs::read(done.join("a")).unwrap(), b"first");
+        assert_eq!(fs::read(done.join("a.2")).unwrap(), b"second");
+        assert!(done.join("a.2.key").exists());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn forgets_vanished_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = inbox(dir.path(), false);
+        let path = Path::new(&inbox.dir).join("a");
+        fs::write(&path, b"piece").unwrap();
+
+        let add_piece = |_: &str, _: &Path| -> Result<(), Error> { Ok(()) };
+        let mut seen = HashMap::new();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert!(seen.contains_key(&path));
+
+        fs::remove_file(&path).unwrap();
+        poll(&inbox, &mut seen, &add_piece).unwrap();
+        assert!(seen.is_empty());
+    }
+}
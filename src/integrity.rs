@@ -0,0 +1,66 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use failure::Error;
+use filecoin_proofs::api::sector_builder::metadata::SealedSectorMetadata;
+
+/// Checks that the sealed replica of a sector exists and has the expected size.
+///
+/// With `checksum` the replica is read completely and compared against the blake2b checksum
+/// stored when it was sealed. This finds damaged data. Returns the reason, if the sector is
+/// faulty.
+///
+/// Recomputing the replica commitment (comm_r) is out of scope: it means sealing the sector
+/// again, and the sector builder offers no way to do that for a sealed sector.
+pub fn check_sector(
+    meta: &SealedSectorMetadata,
+    sector_size: u64,
+    checksum: bool,
+) -> Result<Option<String>, Error> {
+    let path = Path::new(&meta.sector_access);
+
+    let len = match fs::metadata(path) {
+        Ok(data) => data.len(),
+        Err(err) => return Ok(Some(format!("{}: {}", path.display(), err))),
+    };
+    if len != sector_size {
+        return Ok(Some(format!(
+            "{}: expected {} bytes, found {}",
+            path.display(),
+            sector_size,
+            len
+        )));
+    }
+
+    if checksum {
+        let found = blake2b_checksum(path)?;
+        if found[..] != meta.blake2b_checksum[..] {
+            return Ok(Some(format!(
+                "{}: checksum mismatch, expected {}, found {}",
+                path.display(),
+                hex::encode(&meta.blake2b_checksum),
+                hex::encode(&found)
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The blake2b checksum of a file, as stored in the `SealedSectorMetadata`.
+pub fn blake2b_checksum<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Error> {
+    let mut file = File::open(path)?;
+    let mut state = blake2b_simd::State::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        state.update(&buf[..n]);
+    }
+
+    Ok(state.finalize().as_bytes().to_vec())
+}
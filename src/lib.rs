@@ -0,0 +1,29 @@
+#![feature(async_await)]
+
+//! Filecoin proofs & sector management.
+//!
+//! The daemon is started with `server::run`, and can be used with the `FilbaseClient`.
+
+pub mod api;
+pub mod backend;
+pub mod backup;
+pub mod cbor_codec;
+pub mod client;
+mod export;
+#[cfg(feature = "testing")]
+pub mod fuzz;
+pub mod import;
+mod inbox;
+mod integrity;
+pub mod piece;
+mod placement;
+mod policy;
+pub mod server;
+pub mod settings;
+mod space;
+mod store;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod wire;
+
+pub use crate::client::{ClientConfig, FilbaseClient};
@@ -4,18 +4,18 @@
 #[macro_use]
 extern crate prometheus;
 
-use clap::{value_t, values_t};
-use failure::bail;
+use clap::{value_t, values_t, ArgMatches};
+use failure::{bail, format_err};
 use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+use filbase::api::{SectorFilter, SectorMove};
+use filbase::{piece, server, settings};
 
-mod api;
 mod app;
 #[cfg(feature = "benchy")]
 mod benchy;
-mod cbor_codec;
-mod client;
-mod server;
-mod settings;
+mod cli;
 
 #[macro_use]
 mod macros;
@@ -24,41 +24,78 @@ mod macros;
 async fn main() -> Result<(), failure::Error> {
     let matches = app::get_matches();
 
-    // Load settings
-    if let Some(cfg_path) = matches.value_of("config") {
-        println!("loading configuration from {}", cfg_path);
-        settings::Settings::load_config(cfg_path);
+    // The config commands need to work with a broken config.
+    if let ("config", Some(m)) = matches.subcommand() {
+        return match m.subcommand() {
+            ("init", Some(m)) => {
+                let path = m.value_of("PATH").unwrap();
+                cli::config_init(path, m.is_present("force"))
+            }
+            ("show", Some(_m)) => {
+                let overridden = load_settings(&matches)?;
+                cli::config_show(matches.value_of("config"), &overridden)
+            }
+            ("check", Some(m)) => {
+                let file = m.value_of("FILE").unwrap();
+                cli::config_check(file)
+            }
+            ("reload", Some(_m)) => {
+                load_settings(&matches)?;
+                cli::config_reload().await
+            }
+            _ => bail!("Unknown subcommand"),
+        };
     }
 
+    load_settings(&matches)?;
+
     match matches.subcommand() {
         ("daemon", Some(m)) => {
             let prover_id = if m.value_of("prover-id").is_some() {
-                hex_arr!(31, m, "prover-id")?
+                Some(hex_arr!(31, m, "prover-id")?)
             } else {
-                let mut rng = thread_rng();
-                rng.gen()
+                None
             };
+            settings::SETTINGS.read().unwrap().validate_dirs()?;
             let last_used_id = value_t!(m, "last-used-id", u64)?;
             let sector_size = value_t!(m, "sector-size", u64)?;
 
             server::run(last_used_id, prover_id, sector_size).await
         }
+        ("status", Some(_m)) => cli::status().await,
         ("post", Some(m)) => match m.subcommand() {
             ("generate", Some(m)) => {
-                let comm_rs = hex_vec_arr!(32, m, "comm-rs")?;
-                let challenge_seed = hex_arr!(32, m, "challenge-seed")?;
+                let challenge_seed = challenge_seed(m)?;
+
+                if m.is_present("sealed") {
+                    let sectors = if m.is_present("sector-ids") {
+                        SectorFilter::Ids(values_t!(m, "sector-ids", u64)?)
+                    } else if let Some(range) = m.value_of("sector-range") {
+                        parse_sector_range(range)?
+                    } else {
+                        SectorFilter::All
+                    };
 
-                client::post_generate(comm_rs, challenge_seed).await
+                    cli::post_generate_sealed(sectors, challenge_seed).await
+                } else {
+                    let comm_rs = hex_vec_arr!(32, m, "comm-rs")?;
+
+                    cli::post_generate(comm_rs, challenge_seed).await
+                }
             }
             ("verify", Some(m)) => {
                 let sector_size = value_t!(m, "sector-size", u64)?;
                 let proof_partitions = value_t!(m, "proof-partitions", u8)?;
                 let comm_rs = hex_vec_arr!(32, m, "comm-rs")?;
                 let challenge_seed = hex_arr!(32, m, "challenge-seed")?;
-                let proofs = hex_vec_vec!(m, "proof")?;
-                let faults = values_t!(m, "faults", u64)?;
+                let proofs = hex_vec_vec!(m, "proofs")?;
+                let faults = if m.is_present("faults") {
+                    values_t!(m, "faults", u64)?
+                } else {
+                    Vec::new()
+                };
 
-                client::post_verify(
+                cli::post_verify(
                     sector_size,
                     proof_partitions,
                     comm_rs,
@@ -71,7 +108,11 @@ async fn main() -> Result<(), failure::Error> {
             _ => bail!("Unknown subcommand"),
         },
         ("seal", Some(m)) => match m.subcommand() {
-            ("generate", Some(_m)) => client::seal_generate().await,
+            ("generate", Some(_m)) => cli::seal_generate().await,
+            ("verify", Some(m)) if !m.is_present("comm-r") => {
+                let sector_id = value_t!(m, "sector-id", u64)?;
+                cli::seal_verify_sector(sector_id).await
+            }
             ("verify", Some(m)) => {
                 let sector_size = value_t!(m, "sector-size", u64)?;
                 let comm_r = hex_arr!(32, m, "comm-r")?;
@@ -81,7 +122,7 @@ async fn main() -> Result<(), failure::Error> {
                 let sector_id = hex_arr!(31, m, "sector-id")?;
                 let proof = hex_vec!(m, "proof")?;
 
-                client::seal_verify(
+                cli::seal_verify(
                     sector_size,
                     comm_r,
                     comm_d,
@@ -94,17 +135,49 @@ async fn main() -> Result<(), failure::Error> {
             }
             ("status", Some(m)) => {
                 let sector_id = value_t!(m, "sector-id", u64)?;
-                client::seal_status(sector_id).await
+                cli::seal_status(sector_id).await
             }
             _ => bail!("Unknown subcommand"),
         },
         ("sector", Some(m)) => match m.subcommand() {
             ("size", Some(m)) => {
                 let size = value_t!(m, "SIZE", u64)?;
-                client::sector_size(size).await
+                cli::sector_size(size).await
+            }
+            ("list-sealed", Some(_m)) => cli::sector_list_sealed().await,
+            ("list-staged", Some(_m)) => cli::sector_list_staged().await,
+            ("check", Some(m)) => {
+                let checksums = m.is_present("checksums");
+                let output = m.value_of("output");
+
+                cli::sector_check(checksums, output).await
+            }
+            ("move", Some(m)) => {
+                let what = match m.value_of("drain") {
+                    Some(from) => SectorMove::Drain { from: from.into() },
+                    None => SectorMove::Sector {
+                        sector_id: value_t!(m, "sector-id", u64)?,
+                        to: m.value_of("to").unwrap().into(),
+                    },
+                };
+
+                cli::sector_move(what).await
+            }
+            ("export", Some(m)) => {
+                let sector_id = value_t!(m, "sector-id", u64)?;
+                let output = absolute_path(m.value_of("output").unwrap())?;
+
+                cli::sector_export(sector_id, output).await
+            }
+            ("usage", Some(m)) => {
+                let piece_size = if m.is_present("piece-size") {
+                    Some(value_t!(m, "piece-size", u64)?)
+                } else {
+                    None
+                };
+
+                cli::sector_usage(piece_size).await
             }
-            ("list-sealed", Some(_m)) => client::sector_list_sealed().await,
-            ("list-staged", Some(_m)) => client::sector_list_staged().await,
             _ => bail!("Unknown subcommand"),
         },
         ("piece", Some(m)) => match m.subcommand() {
@@ -114,13 +187,77 @@ async fn main() -> Result<(), failure::Error> {
                     .value_of("amount")
                     .map(|s| s.parse().expect("invalid size"));
                 let path = m.value_of("PATH").unwrap();
+                let expected_comm_p = if m.is_present("verify") {
+                    let sector_size = if m.is_present("sector-size") {
+                        value_t!(m, "sector-size", u64)?
+                    } else {
+                        cli::daemon_sector_size().await?
+                    };
+                    let amount = match amount {
+                        Some(amount) => amount,
+                        None => std::fs::metadata(path)?.len(),
+                    };
+                    Some(piece::commitment(path, amount, sector_size)?.comm_p)
+                } else {
+                    None
+                };
 
-                client::piece_add(key, amount, path).await
+                cli::piece_add(key, amount, path, expected_comm_p).await
+            }
+            ("import", Some(m)) => {
+                let source = m.value_of("SOURCE").unwrap();
+
+                cli::piece_import(source).await
+            }
+            ("commit", Some(m)) => {
+                let amount = if m.is_present("amount") {
+                    Some(value_t!(m, "amount", u64)?)
+                } else {
+                    None
+                };
+                let sector_size = if m.is_present("sector-size") {
+                    value_t!(m, "sector-size", u64)?
+                } else {
+                    cli::daemon_sector_size().await?
+                };
+                let path = m.value_of("PATH").unwrap();
+
+                cli::piece_commit(path, amount, sector_size)
             }
             ("read", Some(m)) => {
                 let key = m.value_of("KEY").unwrap();
 
-                client::piece_read(key).await
+                cli::piece_read(key).await
+            }
+            ("list", Some(m)) => {
+                let prefix = m.value_of("prefix").map(Into::into);
+                let offset = value_t!(m, "offset", u64)?;
+                let limit = value_t!(m, "limit", u64)?;
+                let include_removed = m.is_present("all");
+
+                cli::piece_list(prefix, offset, limit, include_removed).await
+            }
+            ("expire", Some(m)) => {
+                let key = m.value_of("KEY").unwrap();
+
+                cli::piece_expire(key).await
+            }
+            ("info", Some(m)) => {
+                let key = m.value_of("KEY").unwrap();
+
+                cli::piece_info(key).await
+            }
+            _ => bail!("Unknown subcommand"),
+        },
+        ("backup", Some(m)) => match m.subcommand() {
+            ("create", Some(m)) => {
+                let output = absolute_path(m.value_of("OUTPUT").unwrap())?;
+                cli::backup_create(output).await
+            }
+            ("restore", Some(m)) => {
+                let archive = m.value_of("ARCHIVE").unwrap();
+                let settings = m.value_of("settings").unwrap();
+                cli::backup_restore(archive, settings, m.is_present("force"))
             }
             _ => bail!("Unknown subcommand"),
         },
@@ -138,3 +275,77 @@ async fn main() -> Result<(), failure::Error> {
         _ => bail!("Unknown subcommand"),
     }
 }
+
+/// Loads and validates the settings, applying the command line overrides. Returns the keys that
+/// were overridden.
+fn load_settings(matches: &ArgMatches) -> Result<Vec<&'static str>, failure::Error> {
+    if let Some(cfg_path) = matches.value_of("config") {
+        println!("loading configuration from {}", cfg_path);
+    }
+    settings::Settings::init(matches.value_of("config"))?;
+
+    let mut settings = settings::SETTINGS.write().unwrap();
+    let mut overridden = Vec::new();
+    if let Some(host) = matches.value_of("host") {
+        settings.host = host.into();
+        overridden.push("host");
+    }
+    if matches.is_present("port") {
+        settings.port = value_t!(matches, "port", usize)?;
+        overridden.push("port");
+    }
+    settings.validate()?;
+
+    Ok(overridden)
+}
+
+/// Reads the challenge seed, either given directly, from a file, hashed from a string or random.
+fn challenge_seed(m: &ArgMatches) -> Result<[u8; 32], failure::Error> {
+    if m.is_present("challenge-seed") {
+        return Ok(hex_arr!(32, m, "challenge-seed")?);
+    }
+
+    if let Some(path) = m.value_of("challenge-seed-file") {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() == 32 {
+            let mut seed = [0u8; 32];
+            seed.copy_from_slice(&bytes);
+            return Ok(seed);
+        }
+
+        let text = String::from_utf8_lossy(&bytes);
+        return <[u8; 32] as hex::FromHex>::from_hex(text.trim()).map_err(|err| {
+            format_err!(
+                "{}: expected 32 raw bytes or 64 hex characters: {}",
+                path,
+                err
+            )
+        });
+    }
+
+    if let Some(s) = m.value_of("challenge-seed-string") {
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&Sha256::digest(s.as_bytes()));
+        return Ok(seed);
+    }
+
+    Ok(thread_rng().gen())
+}
+
+/// Parses an inclusive range of sector ids, in the form `START..END`.
+fn parse_sector_range(range: &str) -> Result<SectorFilter, failure::Error> {
+    let mut parts = range.splitn(2, "..");
+    match (parts.next(), parts.next()) {
+        (Some(start), Some(end)) => Ok(SectorFilter::Range {
+            start: start.trim().parse()?,
+            end: end.trim().parse()?,
+        }),
+        _ => bail!("invalid sector range {:?}, expected START..END", range),
+    }
+}
+
+/// Paths are resolved by the daemon, which might run in a different working directory.
+fn absolute_path(path: &str) -> Result<String, failure::Error> {
+    let path = std::env::current_dir()?.join(path);
+    Ok(path.to_string_lossy().into())
+}
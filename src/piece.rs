@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::path::Path;
+
+use failure::{bail, Error};
+use filecoin_proofs::api as proofs_api;
+use filecoin_proofs::api::safe as fil_api;
+use sector_base::api::bytes_amount::{PaddedBytesAmount, UnpaddedBytesAmount};
+
+/// The commitment of a piece, together with its sizes.
+#[derive(Debug, Clone)]
+pub struct PieceCommitment {
+    pub comm_p: [u8; 32],
+    pub num_bytes: u64,
+    /// The size of the piece after Fr32 padding, as stored in a sector.
+    pub padded_bytes: u64,
+}
+
+/// Computes the piece commitment (comm_p) of the first `num_bytes` of the file at `path`.
+///
+/// Fails if the piece does not fit into a sector of `sector_size` bytes.
+pub fn commitment<P: AsRef<Path>>(
+    path: P,
+    num_bytes: u64,
+    sector_size: u64,
+) -> Result<PieceCommitment, Error> {
+    ensure_fits(num_bytes, sector_size)?;
+
+    let file = File::open(path)?;
+    let comm_p = proofs_api::generate_piece_commitment(file, UnpaddedBytesAmount(num_bytes))?;
+
+    Ok(PieceCommitment {
+        comm_p,
+        num_bytes,
+        padded_bytes: padded_bytes(num_bytes),
+    })
+}
+
+/// The size of a piece of `num_bytes` after Fr32 padding.
+pub fn padded_bytes(num_bytes: u64) -> u64 {
+    u64::from(PaddedBytesAmount::from(UnpaddedBytesAmount(num_bytes)))
+}
+
+/// Fails if a piece of `num_bytes` does not fit into a sector of `sector_size` bytes.
+pub fn ensure_fits(num_bytes: u64, sector_size: u64) -> Result<(), Error> {
+    let max_user_bytes = fil_api::get_max_user_bytes_per_staged_sector(sector_size);
+    if num_bytes > max_user_bytes {
+        bail!(
+            "piece of {} bytes does not fit into a sector of {} bytes, the maximum is {} bytes",
+            num_bytes,
+            sector_size,
+            max_user_bytes
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use failure::{bail, format_err, Error};
+
+use crate::integrity;
+use crate::settings::{Placement, StoragePath};
+
+/// The state of a storage path, used to decide where a sector goes.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: String,
+    pub weight: u32,
+    /// Free bytes on the disk.
+    pub available: u64,
+    /// Bytes used by sectors already placed on this path.
+    pub used: u64,
+    pub capacity: Option<u64>,
+    pub read_only: bool,
+}
+
+impl Candidate {
+    pub fn new(storage: &StoragePath, used: u64) -> Result<Self, Error> {
+        Ok(Candidate {
+            path: storage.path.clone(),
+            weight: storage.weight,
+            available: fs2::available_space(&storage.path)?,
+            used,
+            capacity: storage.capacity,
+            read_only: storage.read_only,
+        })
+    }
+
+    fn fits(&self, size: u64) -> bool {
+        !self.read_only
+            && self.weight > 0
+            && self.available >= size
+            && self.capacity.map(|c| self.used + size <= c).unwrap_or(true)
+    }
+}
+
+/// Picks the storage path for a new sector of `size` bytes.
+///
+/// `counter` is the number of sectors placed so far, it drives the weighted round-robin. For
+/// `FreeSpace` the path with the most free space, scaled by its weight, wins.
+pub fn choose(
+    placement: &Placement,
+    candidates: &[Candidate],
+    size: u64,
+    counter: usize,
+) -> Option<String> {
+    let fitting: Vec<_> = candidates.iter().filter(|c| c.fits(size)).collect();
+    if fitting.is_empty() {
+        return None;
+    }
+
+    let chosen = match placement {
+        Placement::FreeSpace => fitting
+            .iter()
+            .max_by_key(|c| u128::from(c.available) * u128::from(c.weight))?,
+        Placement::RoundRobin => {
+            let slots: Vec<_> = fitting
+                .iter()
+                .flat_map(|c| std::iter::repeat(c).take(c.weight as usize))
+                .collect();
+            slots[counter % slots.len()]
+        }
+    };
+
+    Some(chosen.path.clone())
+}
+
+/// Moves a sealed sector file into `dir`, leaving a symlink at its old location, so the sector
+/// builder still finds it.
+///
+/// The copy is verified against the checksum of the original before anything is replaced.
+/// The symlink replaces the original file atomically. If `source` already is a symlink, it is
+/// pointed to the new location and the previous target is removed.
+pub fn relocate<P: AsRef<Path>, Q: AsRef<Path>>(source: P, dir: Q) -> Result<PathBuf, Error> {
+    let source = source.as_ref();
+    let is_link = fs::symlink_metadata(source)?.file_type().is_symlink();
+    let current = fs::canonicalize(source)?;
+    let name = source
+        .file_name()
+        .ok_or_else(|| format_err!("invalid sector path: {}", source.display()))?;
+    let target = fs::canonicalize(dir.as_ref())?.join(name);
+    if target == current {
+        bail!(
+            "{} is already stored in {}",
+            source.display(),
+            dir.as_ref().display()
+        );
+    }
+
+    let partial = target.with_extension("partial");
+    fs::copy(&current, &partial)?;
+    fs::File::open(&partial)?.sync_all()?;
+
+    let expected = integrity::blake2b_checksum(&current)?;
+    if integrity::blake2b_checksum(&partial)? != expected {
+        fs::remove_file(&partial)?;
+        bail!(
+            "checksum mismatch after copying {} to {}",
+            current.display(),
+            target.display()
+        );
+    }
+    fs::rename(&partial, &target)?;
+
+    let link = source.with_extension("link");
+    let _ = fs::remove_file(&link);
+    symlink(&target, &link)?;
+    fs::rename(&link, source)?;
+
+    // A regular file was replaced by the symlink already.
+    if is_link {
+        fs::remove_file(&current)?;
+    }
+
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, weight: u32, available: u64) -> Candidate {
+        Candidate {
+            path: path.into(),
+            weight,
+            available,
+            used: 0,
+            capacity: None,
+            read_only: false,
+        }
+    }
+
+    #[test]
+    fn free_space() {
+        let candidates = vec![candidate("a", 1, 100), candidate("b", 1, 300)];
+        assert_eq!(
+            choose(&Placement::FreeSpace, &candidates, 10, 0),
+            Some("b".into())
+        );
+        assert_eq!(choose(&Placement::FreeSpace, &candidates, 1000, 0), None);
+    }
+
+    #[test]
+    fn round_robin() {
+        let mut candidates = vec![candidate("a", 2, 100), candidate("b", 1, 100)];
+        let chosen: Vec<_> = (0..3)
+            .map(|i| choose(&Placement::RoundRobin, &candidates, 10, i).unwrap())
+            .collect();
+        assert_eq!(chosen, vec!["a", "a", "b"]);
+
+        candidates[0].read_only = true;
+        assert_eq!(
+            choose(&Placement::RoundRobin, &candidates, 10, 0),
+            Some("b".into())
+        );
+
+        candidates[1].capacity = Some(5);
+        assert_eq!(choose(&Placement::RoundRobin, &candidates, 10, 0), None);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+
+use failure::{bail, Error};
+use filecoin_proofs::api::sector_builder::metadata::{SealStatus, StagedSectorMetadata};
+
+use crate::api::PolicyDecision;
+use crate::settings::SealingPolicy;
+use crate::store::PieceRecord;
+
+/// Decides whether the staged sectors should be sealed now.
+///
+/// `now` is given in seconds since the unix epoch.
+pub fn evaluate(
+    policy: &SealingPolicy,
+    staged: &[StagedSectorMetadata],
+    pieces: &BTreeMap<String, PieceRecord>,
+    max_user_bytes: u64,
+    now: u64,
+) -> Result<PolicyDecision, Error> {
+    let windowed = policy
+        .windows
+        .iter()
+        .map(|w| parse_window(w))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .any(|(start, end)| in_window(start, end, minute_of_day(now)));
+
+    let sealing = staged
+        .iter()
+        .filter(|meta| match meta.seal_status {
+            SealStatus::Sealing => true,
+            _ => false,
+        })
+        .count();
+    let open: Vec<_> = staged
+        .iter()
+        .filter(|meta| match meta.seal_status {
+            SealStatus::Pending => !meta.pieces.is_empty(),
+            _ => false,
+        })
+        .collect();
+
+    let mut due = Vec::new();
+    let mut reasons = Vec::new();
+    for meta in &open {
+        let used = used_bytes(meta);
+        let oldest = meta
+            .pieces
+            .iter()
+            .filter_map(|p| pieces.get(&p.piece_key))
+            .map(|p| p.added_at)
+            .min();
+
+        // Each condition is checked on its own, any of them makes the sector due.
+        let mut met = Vec::new();
+        if let Some(percent) = policy.fill_percent {
+            if used * 100 >= max_user_bytes * u64::from(percent) {
+                met.push(format!("is {} of {} bytes full", used, max_user_bytes));
+            }
+        }
+        if let (Some(max_age), Some(oldest)) = (policy.max_piece_age, oldest) {
+            if now.saturating_sub(oldest) >= max_age {
+                met.push(format!("holds a piece older than {}s", max_age));
+            }
+        }
+        if windowed {
+            met.push("is open during a sealing window".into());
+        }
+
+        if !met.is_empty() {
+            due.push(meta.sector_id);
+            reasons.push(format!("sector {} {}", meta.sector_id, met.join(" and ")));
+        }
+    }
+
+    // Sealing always starts for all open sectors at once, so it waits while that would exceed
+    // `max_concurrent_seals`.
+    let seal = !due.is_empty() && sealing + open.len() <= policy.max_concurrent_seals;
+    let started: Vec<_> = if seal {
+        open.iter().map(|meta| meta.sector_id).collect()
+    } else {
+        Vec::new()
+    };
+    let reason = if due.is_empty() {
+        "no sector is due".into()
+    } else if !seal {
+        format!(
+            "waiting, sealing all {} open sectors would exceed max_concurrent_seals ({} sealing): {}",
+            open.len(),
+            sealing,
+            reasons.join(", ")
+        )
+    } else {
+        format!("sealing sectors {:?}: {}", started, reasons.join(", "))
+    };
+
+    Ok(PolicyDecision {
+        at: now,
+        due,
+        seal,
+        started,
+        reason,
+    })
+}
+
+/// The number of user bytes taken by the pieces in a staged sector.
+pub fn used_bytes(meta: &StagedSectorMetadata) -> u64 {
+    meta.pieces.iter().map(|p| u64::from(p.num_bytes)).sum()
+}
+
+/// Parses a window of the form `HH:MM-HH:MM` into minutes of the day.
+pub fn parse_window(window: &str) -> Result<(u32, u32), Error> {
+    let mut parts = window.splitn(2, '-');
+    match (parts.next(), parts.next()) {
+        (Some(start), Some(end)) => Ok((parse_time(start)?, parse_time(end)?)),
+        _ => bail!("invalid sealing window {:?}, expected HH:MM-HH:MM", window),
+    }
+}
+
+fn parse_time(time: &str) -> Result<u32, Error> {
+    let mut parts = time.trim().splitn(2, ':');
+    let (hours, minutes): (u32, u32) = match (parts.next(), parts.next()) {
+        (Some(h), Some(m)) => (h.parse()?, m.parse()?),
+        _ => bail!("invalid time {:?}, expected HH:MM", time),
+    };
+    if hours > 23 || minutes > 59 {
+        bail!("invalid time {:?}, expected HH:MM", time);
+    }
+
+    Ok(hours * 60 + minutes)
+}
+
+fn minute_of_day(now: u64) -> u32 {
+    ((now % 86_400) / 60) as u32
+}
+
+/// Windows ending before they start wrap around midnight.
+fn in_window(start: u32, end: u32, minute: u32) -> bool {
+    if start <= end {
+        start <= minute && minute < end
+    } else {
+        minute >= start || minute < end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filecoin_proofs::api::sector_builder::metadata::PieceMetadata;
+    use sector_base::api::bytes_amount::UnpaddedBytesAmount;
+
+    /// A sector with one piece `s<id>` of `bytes`, added at 0.
+    fn sector(sector_id: u64, bytes: u64, seal_status: SealStatus) -> StagedSectorMetadata {
+        StagedSectorMetadata {
+            sector_id,
+            sector_access: format!("staged-{}", sector_id),
+            pieces: vec![PieceMetadata {
+                piece_key: format!("s{}", sector_id),
+                num_bytes: UnpaddedBytesAmount(bytes),
+            }],
+            seal_status,
+        }
+    }
+
+    fn pieces(staged: &[StagedSectorMetadata]) -> BTreeMap<String, PieceRecord> {
+        staged
+            .iter()
+            .flat_map(|meta| meta.pieces.iter())
+            .map(|p| {
+                (
+                    p.piece_key.clone(),
+                    PieceRecord {
+                        added_at: 0,
+                        comm_p: None,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn windows() {
+        assert_eq!(parse_window("08:30-17:00").unwrap(), (510, 1020));
+        assert!(parse_window("8-17").is_err());
+        assert!(parse_window("24:00-01:00").is_err());
+
+        assert!(in_window(510, 1020, 600));
+        assert!(!in_window(510, 1020, 1020));
+        // 22:00-06:00
+        assert!(in_window(1320, 360, 1380));
+        assert!(in_window(1320, 360, 0));
+        assert!(!in_window(1320, 360, 720));
+    }
+
+    #[test]
+    fn nothing_staged() {
+        let policy = SealingPolicy {
+            fill_percent: Some(90),
+            ..Default::default()
+        };
+        let decision = evaluate(&policy, &[], &BTreeMap::new(), 1024, 0).unwrap();
+        assert!(!decision.seal);
+        assert!(decision.due.is_empty());
+    }
+
+    #[test]
+    fn fill() {
+        let policy = SealingPolicy {
+            fill_percent: Some(90),
+            ..Default::default()
+        };
+        let staged = vec![
+            sector(1, 1000, SealStatus::Pending),
+            sector(2, 500, SealStatus::Pending),
+        ];
+
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 0).unwrap();
+        assert!(decision.seal);
+        assert_eq!(decision.due, vec![1]);
+        // Sector 2 is not due, but sealed together with it.
+        assert_eq!(decision.started, vec![1, 2]);
+    }
+
+    #[test]
+    fn age() {
+        let policy = SealingPolicy {
+            max_piece_age: Some(3600),
+            ..Default::default()
+        };
+        let staged = vec![sector(1, 10, SealStatus::Pending)];
+
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 3599).unwrap();
+        assert!(!decision.seal);
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 3600).unwrap();
+        assert!(decision.seal);
+        assert_eq!(decision.due, vec![1]);
+    }
+
+    #[test]
+    fn window() {
+        let policy = SealingPolicy {
+            windows: vec!["22:00-06:00".into()],
+            ..Default::default()
+        };
+        let staged = vec![sector(1, 10, SealStatus::Pending)];
+
+        // 12:00 and 23:00 UTC.
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 12 * 3600).unwrap();
+        assert!(!decision.seal);
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 23 * 3600).unwrap();
+        assert!(decision.seal);
+    }
+
+    #[test]
+    fn age_and_window() {
+        let policy = SealingPolicy {
+            max_piece_age: Some(24 * 3600),
+            windows: vec!["22:00-06:00".into()],
+            ..Default::default()
+        };
+        let staged = vec![sector(1, 10, SealStatus::Pending)];
+
+        // The piece is too young, but the window is open at 23:00.
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 12 * 3600).unwrap();
+        assert!(!decision.seal);
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 23 * 3600).unwrap();
+        assert!(decision.seal);
+        assert_eq!(decision.due, vec![1]);
+
+        // Outside the window, the age alone is enough.
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 36 * 3600).unwrap();
+        assert!(decision.seal);
+        assert!(decision.reason.contains("older than"));
+    }
+
+    #[test]
+    fn concurrent_seals() {
+        let policy = SealingPolicy {
+            fill_percent: Some(90),
+            max_concurrent_seals: 1,
+            ..Default::default()
+        };
+
+        // All open sectors would be sealed at once, more than allowed.
+        let staged = vec![
+            sector(1, 1000, SealStatus::Pending),
+            sector(2, 1000, SealStatus::Pending),
+        ];
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 0).unwrap();
+        assert!(!decision.seal);
+        assert!(decision.started.is_empty());
+        assert_eq!(decision.due, vec![1, 2]);
+        assert!(decision.reason.starts_with("waiting"));
+
+        let staged = vec![sector(1, 1000, SealStatus::Pending)];
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 0).unwrap();
+        assert!(decision.seal);
+
+        // Wait while another sector is sealing.
+        let staged = vec![
+            sector(1, 1000, SealStatus::Sealing),
+            sector(2, 1000, SealStatus::Pending),
+        ];
+        let decision = evaluate(&policy, &staged, &pieces(&staged), 1016, 0).unwrap();
+        assert!(!decision.seal);
+        assert_eq!(decision.due, vec![2]);
+        assert!(decision.reason.starts_with("waiting"));
+    }
+}
@@ -1,89 +1,480 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
+use failure::bail;
 use filecoin_proofs::api::safe as fil_api;
-use filecoin_proofs::api::sector_builder::SectorBuilder;
+use filecoin_proofs::api::sector_builder::metadata::{
+    SealStatus, SealedSectorMetadata, StagedSectorMetadata,
+};
+use futures::channel::oneshot;
 use futures::prelude::*;
 use futures_codec::Framed;
+use rand::{thread_rng, Rng};
 use runtime::net::{TcpListener, TcpStream};
-use sector_base::api::porep_proof_partitions::PoRepProofPartitions;
-use sector_base::api::post_proof_partitions::PoStProofPartitions;
-use sector_base::api::sector_class::SectorClass;
-use sector_base::api::sector_size::SectorSize;
 
 use crate::api::*;
+use crate::backend::{sector_id_as_bytes, ProofsBackend, SectorBackend};
+use crate::backup;
 use crate::cbor_codec::Codec;
-use crate::settings::SETTINGS;
+use crate::export;
+use crate::inbox;
+use crate::integrity;
+use crate::piece;
+use crate::placement;
+use crate::policy;
+use crate::settings::{Settings, StoragePath, SETTINGS};
+use crate::space;
+use crate::store::{self, PieceRecord, Removal, Store};
 
+/// The largest request accepted from a connection. Requests carry proofs and commitments, but
+/// never sector or piece data.
+pub const MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+/// Runs the daemon.
+///
+/// Without a `prover_id`, the one persisted in the `metadata_dir` is used, or a new random one is
+/// created and persisted.
 pub async fn run(
     last_used_id: u64,
-    prover_id: [u8; 31],
+    prover_id: Option<[u8; 31]>,
     sector_size: u64,
 ) -> Result<(), failure::Error> {
+    run_with_backend(prover_id, sector_size, |cfg, prover_id| {
+        let backend = ProofsBackend::new(cfg, sector_size, last_used_id, prover_id)?;
+        Ok(Box::new(backend))
+    })
+    .await
+}
+
+/// Runs the daemon on the backend created by `backend`, from the settings and the prover id.
+pub async fn run_with_backend<F>(
+    prover_id: Option<[u8; 31]>,
+    sector_size: u64,
+    backend: F,
+) -> Result<(), failure::Error>
+where
+    F: FnOnce(&Settings, [u8; 31]) -> Result<Box<dyn SectorBackend>, failure::Error>,
+{
     let cfg = SETTINGS.clone().read().unwrap().clone();
+    let server = Server::bind(&cfg, prover_id, sector_size, backend)?;
+    println!("API listening on {}", server.local_addr()?);
 
-    let mut listener = TcpListener::bind(cfg.server())?;
-    println!("API listening on {}", listener.local_addr()?);
+    server.serve().await
+}
 
-    let sb = fil_api::init_sector_builder(
-        SectorClass(
-            SectorSize(sector_size),
-            PoRepProofPartitions(cfg.porep_partitions),
-            PoStProofPartitions(cfg.post_partitions),
-        ),
-        last_used_id,
-        &cfg.metadata_dir,
-        prover_id,
-        &cfg.sealed_sector_dir,
-        &cfg.staged_sector_dir,
-        cfg.max_num_staged_sectors,
-    )?;
-    let sb = Arc::new(Mutex::new(sb));
+/// A daemon bound to its address.
+pub struct Server {
+    listener: TcpListener,
+    daemon: Arc<Daemon>,
+}
 
-    println!(
-        "Sector builder started, with ID: {}",
-        hex::encode(&prover_id)
-    );
+impl Server {
+    /// Starts the backend and binds to the host and port of `cfg`. Port 0 binds to any free port.
+    ///
+    /// The background tasks still use the global `SETTINGS`.
+    pub fn bind<F>(
+        cfg: &Settings,
+        prover_id: Option<[u8; 31]>,
+        sector_size: u64,
+        backend: F,
+    ) -> Result<Self, failure::Error>
+    where
+        F: FnOnce(&Settings, [u8; 31]) -> Result<Box<dyn SectorBackend>, failure::Error>,
+    {
+        let listener = TcpListener::bind(cfg.server())?;
+        let daemon = Arc::new(Daemon::new(cfg, prover_id, sector_size, backend)?);
 
-    let mut incoming = listener.incoming();
+        Ok(Server { listener, daemon })
+    }
 
-    while let Some(stream) = incoming.next().await {
-        runtime::spawn(handle(stream?, sb.clone())).await?;
+    /// The address the daemon listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr, failure::Error> {
+        Ok(self.listener.local_addr()?)
     }
 
-    Ok(())
+    pub fn prover_id(&self) -> [u8; 31] {
+        self.daemon.prover_id
+    }
+
+    /// Handles a single request, as if it was received from a connection.
+    pub fn handle(&self, request: Request) -> Response {
+        response(request, &self.daemon)
+    }
+
+    /// Starts the background tasks and serves requests until the listener fails.
+    pub async fn serve(self) -> Result<(), failure::Error> {
+        let daemon = self.daemon.clone();
+        println!(
+            "Sector builder started, with ID: {}",
+            hex::encode(&daemon.prover_id)
+        );
+
+        let d = daemon.clone();
+        thread::spawn(move || run_sealing_policy(d));
+
+        let d = daemon.clone();
+        thread::spawn(move || run_integrity_checks(d));
+
+        let d = daemon.clone();
+        thread::spawn(move || run_placement(d));
+
+        let signals = signal_hook::iterator::Signals::new(&[signal_hook::SIGHUP])?;
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                match Settings::reload() {
+                    Ok(reload) => print_reload(&reload),
+                    Err(err) => println!("reloading the settings failed: {}", err),
+                }
+            }
+        });
+
+        let d = daemon;
+        thread::spawn(move || {
+            inbox::watch(|key, path| {
+                let req = Request::PieceAdd {
+                    key: key.into(),
+                    amount: None,
+                    path: path.to_string_lossy().into(),
+                };
+                respond(req, &d).map(|_| ())
+            })
+        });
+
+        self.serve_requests().await
+    }
+
+    /// Serves requests until the listener fails, without starting the background tasks. They
+    /// follow the global `SETTINGS`, which belong to a single daemon per process.
+    pub async fn serve_requests(self) -> Result<(), failure::Error> {
+        let Server {
+            mut listener,
+            daemon,
+        } = self;
+
+        let mut incoming = listener.incoming();
+
+        while let Some(stream) = incoming.next().await {
+            // Clients keep their connection open, so connections are handled concurrently.
+            let handler = handle(stream?, daemon.clone());
+            runtime::spawn(async move {
+                if let Err(err) = handler.await {
+                    println!("connection failed: {}", err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn print_reload(reload: &ConfigReload) {
+    println!("settings reloaded, applied: {:?}", reload.applied);
+    if !reload.ignored.is_empty() {
+        println!("ignored until restart: {:?}", reload.ignored);
+    }
+}
+
+/// Shared state of a running daemon.
+struct Daemon {
+    sb: Mutex<Box<dyn SectorBackend>>,
+    store: Mutex<Store>,
+    prover_id: [u8; 31],
+    sector_size: u64,
+    porep_partitions: u8,
+    post_partitions: u8,
+    max_num_staged_sectors: u8,
+    metadata_dir: String,
+    sealed_sector_dir: String,
+    staged_sector_dir: String,
+    /// The number of sectors placed on the storage paths.
+    placement_counter: AtomicUsize,
+    busy: Mutex<Busy>,
+    last_policy_decision: Mutex<Option<PolicyDecision>>,
 }
 
-async fn handle(stream: TcpStream, sb: Arc<Mutex<SectorBuilder>>) -> Result<(), failure::Error> {
+impl Daemon {
+    fn new<F>(
+        cfg: &Settings,
+        prover_id: Option<[u8; 31]>,
+        sector_size: u64,
+        backend: F,
+    ) -> Result<Self, failure::Error>
+    where
+        F: FnOnce(&Settings, [u8; 31]) -> Result<Box<dyn SectorBackend>, failure::Error>,
+    {
+        let mut store = Store::open(&cfg.metadata_dir)?;
+        let prover_id = match (prover_id, store.data().prover_id) {
+            (Some(given), Some(stored)) if given != stored => bail!(
+                "prover id {} does not match the prover id {} stored in {}",
+                hex::encode(&given[..]),
+                hex::encode(&stored[..]),
+                cfg.metadata_dir
+            ),
+            (Some(id), _) | (None, Some(id)) => id,
+            (None, None) => thread_rng().gen(),
+        };
+        if store.data().prover_id.is_none() {
+            store.update(|data| data.prover_id = Some(prover_id))?;
+        }
+
+        Ok(Daemon {
+            sb: Mutex::new(backend(cfg, prover_id)?),
+            store: Mutex::new(store),
+            prover_id,
+            sector_size,
+            porep_partitions: cfg.porep_partitions,
+            post_partitions: cfg.post_partitions,
+            max_num_staged_sectors: cfg.max_num_staged_sectors,
+            metadata_dir: cfg.metadata_dir.clone(),
+            sealed_sector_dir: cfg.sealed_sector_dir.clone(),
+            staged_sector_dir: cfg.staged_sector_dir.clone(),
+            placement_counter: AtomicUsize::new(0),
+            busy: Mutex::new(Busy::default()),
+            last_policy_decision: Mutex::new(None),
+        })
+    }
+}
+
+/// Sealed sectors which are currently in use.
+#[derive(Debug, Default)]
+struct Busy {
+    /// Part of an in-flight PoSt.
+    proving: HashSet<u64>,
+    /// Being moved to another storage path.
+    moving: HashSet<u64>,
+}
+
+/// Marks sectors as busy, until dropped.
+struct BusyGuard<'a> {
+    daemon: &'a Daemon,
+    sector_ids: Vec<u64>,
+    proving: bool,
+}
+
+impl<'a> BusyGuard<'a> {
+    /// Fails if any of the sectors is being moved.
+    fn proving(daemon: &'a Daemon, sector_ids: Vec<u64>) -> Result<Self, failure::Error> {
+        let mut busy = daemon.busy.lock().unwrap();
+        if let Some(id) = sector_ids.iter().find(|id| busy.moving.contains(*id)) {
+            bail!("sector {} is being moved", id);
+        }
+        busy.proving.extend(&sector_ids);
+
+        Ok(BusyGuard {
+            daemon,
+            sector_ids,
+            proving: true,
+        })
+    }
+
+    /// Fails if the sector is part of an in-flight PoSt, or already being moved.
+    fn moving(daemon: &'a Daemon, sector_id: u64) -> Result<Self, failure::Error> {
+        let mut busy = daemon.busy.lock().unwrap();
+        if busy.proving.contains(&sector_id) {
+            bail!("sector {} is part of an in-flight PoSt", sector_id);
+        }
+        if !busy.moving.insert(sector_id) {
+            bail!("sector {} is already being moved", sector_id);
+        }
+
+        Ok(BusyGuard {
+            daemon,
+            sector_ids: vec![sector_id],
+            proving: false,
+        })
+    }
+}
+
+impl<'a> Drop for BusyGuard<'a> {
+    fn drop(&mut self) {
+        let mut busy = self.daemon.busy.lock().unwrap();
+        let set = if self.proving {
+            &mut busy.proving
+        } else {
+            &mut busy.moving
+        };
+        for id in &self.sector_ids {
+            set.remove(id);
+        }
+    }
+}
+
+async fn handle(stream: TcpStream, daemon: Arc<Daemon>) -> Result<(), failure::Error> {
     println!("connected");
-    let mut framed = Framed::new(stream, Codec::new());
+    let codec = Codec::new().max_frame_size(MAX_REQUEST_SIZE);
+    let mut framed = Framed::new(stream, codec);
+
+    // Both ends have to speak the same protocol version, before any other request.
+    match framed.next().await {
+        Some(Ok(Request::Handshake { protocol_version })) => {
+            let handshake = Response::Handshake {
+                protocol_version: PROTOCOL_VERSION,
+            };
+            framed.send(handshake).await?;
+            if protocol_version != PROTOCOL_VERSION {
+                return Ok(());
+            }
+        }
+        Some(Ok(res)) => {
+            let err = format!(
+                "expected a handshake with protocol version {}, got {:?}",
+                PROTOCOL_VERSION, res
+            );
+            framed.send(Response::Err(err)).await?;
+            return Ok(());
+        }
+        Some(Err(err)) => return Err(err),
+        None => return Ok(()),
+    }
 
     while let Some(res) = framed.next().await {
         let res = res?;
         println!("Got: {:?}", res);
-        let response = match respond(res, sb.clone()) {
-            Ok(response) => response,
-            Err(err) => Response::Err(format!("{:?}", err)),
+        let response = if is_long_running(&res) {
+            response_in_thread(res, daemon.clone()).await
+        } else {
+            response(res, &daemon)
         };
-
         framed.send(response).await?;
     }
 
     Ok(())
 }
 
-fn respond(res: Request, sb: Arc<Mutex<SectorBuilder>>) -> Result<Response, failure::Error> {
+/// Requests which read or copy whole pieces or sectors, or run proofs. They are handled on their
+/// own thread, so that they don't block the other connections.
+fn is_long_running(res: &Request) -> bool {
+    match res {
+        Request::PostGenerate { .. }
+        | Request::PostGenerateSealed { .. }
+        | Request::PostVerify { .. }
+        | Request::SealVerify { .. }
+        | Request::SealVerifySector(_)
+        | Request::SealAllStaged
+        | Request::SectorCheck { .. }
+        | Request::SectorExport { .. }
+        | Request::SectorMove(_)
+        | Request::BackupCreate { .. }
+        | Request::PieceAdd { .. }
+        | Request::PieceRead(_) => true,
+        _ => false,
+    }
+}
+
+/// Handles a request on its own thread, resolving once it is done.
+async fn response_in_thread(res: Request, daemon: Arc<Daemon>) -> Response {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = tx.send(response(res, &daemon));
+    });
+
+    rx.await
+        .unwrap_or_else(|_| Response::Err("handling the request failed".into()))
+}
+
+fn response(res: Request, daemon: &Daemon) -> Response {
+    match respond(res, daemon) {
+        Ok(response) => response,
+        Err(err) => match err.downcast::<InsufficientSpace>() {
+            Ok(err) => Response::InsufficientSpace(err),
+            Err(err) => Response::Err(format!("{:?}", err)),
+        },
+    }
+}
+
+fn respond(res: Request, daemon: &Daemon) -> Result<Response, failure::Error> {
+    let sb = &daemon.sb;
     let response = match res {
+        Request::Handshake { .. } => Response::Handshake {
+            protocol_version: PROTOCOL_VERSION,
+        },
+        Request::Status => Response::Status(Status {
+            prover_id: daemon.prover_id,
+            sector_size: daemon.sector_size,
+            max_user_bytes: fil_api::get_max_user_bytes_per_staged_sector(daemon.sector_size),
+            sealing_policy: daemon.last_policy_decision.lock().unwrap().clone(),
+            disks: disk_status(daemon)?,
+            faults: daemon.store.lock().unwrap().data().faults.clone(),
+        }),
+
         // -- Post
         Request::PostGenerate {
             comm_rs,
             challenge_seed,
         } => {
-            let out = fil_api::generate_post(&sb.lock().unwrap(), comm_rs, &challenge_seed)?;
+            let sector_ids = sb
+                .lock()
+                .unwrap()
+                .sealed_sectors()?
+                .into_iter()
+                .filter(|meta| comm_rs.contains(&meta.comm_r))
+                .map(|meta| meta.sector_id)
+                .collect();
+            let _guard = BusyGuard::proving(daemon, sector_ids)?;
+
+            let out = sb.lock().unwrap().generate_post(comm_rs, &challenge_seed)?;
             Response::PostGenerate {
                 proofs: out.proofs,
                 faults: out.faults,
             }
         }
+        Request::PostGenerateSealed {
+            sectors,
+            challenge_seed,
+        } => {
+            let mut sealed = sb.lock().unwrap().sealed_sectors()?;
+            sealed.retain(|meta| sectors.matches(meta.sector_id));
+            sealed.sort_by_key(|meta| meta.sector_id);
+            if sealed.is_empty() {
+                bail!("no sealed sectors match {:?}", sectors);
+            }
+
+            // Sectors with faults known from the integrity checks are left out before proving,
+            // so that the returned inputs verify.
+            let known_faults: HashSet<_> = daemon
+                .store
+                .lock()
+                .unwrap()
+                .data()
+                .faults
+                .iter()
+                .map(|fault| fault.sector_id)
+                .collect();
+            let (faulty, sealed): (Vec<_>, Vec<_>) = sealed
+                .into_iter()
+                .partition(|meta| known_faults.contains(&meta.sector_id));
+            let excluded: Vec<_> = faulty.iter().map(|meta| meta.sector_id).collect();
+            if sealed.is_empty() {
+                bail!("all sealed sectors matching {:?} are faulty", sectors);
+            }
+
+            let sector_ids: Vec<_> = sealed.iter().map(|meta| meta.sector_id).collect();
+            let comm_rs: Vec<_> = sealed.iter().map(|meta| meta.comm_r).collect();
+            let _guard = BusyGuard::proving(daemon, sector_ids.clone())?;
+
+            let out = sb
+                .lock()
+                .unwrap()
+                .generate_post(comm_rs.clone(), &challenge_seed)?;
+
+            Response::PostGenerateSealed {
+                sector_size: daemon.sector_size,
+                proof_partitions: daemon.post_partitions,
+                sector_ids,
+                comm_rs,
+                challenge_seed,
+                proofs: out.proofs,
+                faults: out.faults,
+                excluded,
+            }
+        }
         Request::PostVerify {
             sector_size,
             proof_partitions,
@@ -92,7 +483,8 @@ fn respond(res: Request, sb: Arc<Mutex<SectorBuilder>>) -> Result<Response, fail
             proofs,
             faults,
         } => {
-            let valid = fil_api::verify_post(
+            ensure_sector_size(sector_size)?;
+            let valid = daemon.sb.lock().unwrap().verify_post(
                 sector_size,
                 proof_partitions,
                 comm_rs,
@@ -114,7 +506,8 @@ fn respond(res: Request, sb: Arc<Mutex<SectorBuilder>>) -> Result<Response, fail
             sector_id,
             proof,
         } => {
-            let valid = fil_api::verify_seal(
+            ensure_sector_size(sector_size)?;
+            let valid = daemon.sb.lock().unwrap().verify_seal(
                 sector_size,
                 comm_r,
                 comm_d,
@@ -126,28 +519,85 @@ fn respond(res: Request, sb: Arc<Mutex<SectorBuilder>>) -> Result<Response, fail
 
             Response::SealVerify(valid)
         }
+        Request::SealVerifySector(sector_id) => {
+            let result = verify_sealed_sector(daemon, sector_id)?;
+            Response::SealVerifySector(result)
+        }
         Request::SealAllStaged => {
-            fil_api::seal_all_staged_sectors(&sb.lock().unwrap())?;
+            let sb = sb.lock().unwrap();
+            let open = open_sectors(&sb.staged_sectors()?);
+            ensure_seal_space(daemon, open.len() as u64)?;
+
+            sb.seal_all_staged()?;
             Response::SealAllStaged
         }
         Request::SealStatus(id) => {
-            let status = fil_api::get_seal_status(&sb.lock().unwrap(), id)?;
+            let status = sb.lock().unwrap().seal_status(id)?;
             Response::SealStatus(status)
         }
 
         // -- Sector
         Request::SectorSize(sector_size) => {
+            ensure_sector_size(sector_size)?;
             let size = fil_api::get_max_user_bytes_per_staged_sector(sector_size);
             Response::SectorSize(size)
         }
         Request::SectorListSealed => {
-            let list = fil_api::get_sealed_sectors(&sb.lock().unwrap())?;
+            let list = sb
+                .lock()
+                .unwrap()
+                .sealed_sectors()?
+                .into_iter()
+                .map(|meta| SealedSector {
+                    path: sector_location(daemon, meta.sector_id),
+                    meta,
+                })
+                .collect();
+
             Response::SectorListSealed(list)
         }
         Request::SectorListStaged => {
-            let list = fil_api::get_staged_sectors(&sb.lock().unwrap())?;
+            let list = sb.lock().unwrap().staged_sectors()?;
             Response::SectorListStaged(list)
         }
+        Request::BackupCreate { path } => {
+            create_backup(daemon, &path)?;
+            Response::BackupCreate
+        }
+        Request::ConfigReload => {
+            let reload = Settings::reload()?;
+            print_reload(&reload);
+            Response::ConfigReload(reload)
+        }
+        Request::SectorExport { sector_id, path } => {
+            let sealed = sb.lock().unwrap().sealed_sectors()?;
+            let meta = match sealed.iter().find(|meta| meta.sector_id == sector_id) {
+                Some(meta) => meta,
+                None => bail!("unknown sealed sector: {}", sector_id),
+            };
+            let _guard = BusyGuard::proving(daemon, vec![sector_id])?;
+
+            export::export(
+                meta,
+                &daemon.prover_id,
+                daemon.sector_size,
+                daemon.porep_partitions,
+                &path,
+            )?;
+            Response::SectorExport
+        }
+        Request::SectorMove(what) => {
+            let moved = move_sectors(daemon, what)?;
+            Response::SectorMove(moved)
+        }
+        Request::SectorCheck { checksums } => {
+            let faults = check_sectors(daemon, checksums)?;
+            Response::SectorCheck(faults)
+        }
+        Request::SectorUsage(piece_size) => {
+            let usage = sector_usage(daemon, piece_size)?;
+            Response::SectorUsage(usage)
+        }
 
         // -- Piece
         Request::PieceAdd { key, amount, path } => {
@@ -157,20 +607,795 @@ fn respond(res: Request, sb: Arc<Mutex<SectorBuilder>>) -> Result<Response, fail
                 get_file_size(&path)
             }?;
 
-            let id = fil_api::add_piece(&sb.lock().unwrap(), &key, amount, &path)?;
-            Response::PieceAdd(id)
+            if let Some(removal) = daemon.store.lock().unwrap().data().removed.get(&key) {
+                bail!(
+                    "piece {} is {:?}, its key can not be reused",
+                    key,
+                    removal.kind
+                );
+            }
+
+            // The commitment is computed over a copy of the piece, which is then handed to the
+            // sector builder, so that it matches the stored bytes even if the file changes. The
+            // copy and the staged piece both take space in the `staged_sector_dir`.
+            piece::ensure_fits(amount, daemon.sector_size)?;
+            space::ensure_available(
+                &daemon.staged_sector_dir,
+                amount + piece::padded_bytes(amount),
+            )?;
+            let snapshot = PieceSnapshot::create(daemon, &path, amount)?;
+            let commitment = piece::commitment(&snapshot.path, amount, daemon.sector_size)?;
+            let id =
+                sb.lock()
+                    .unwrap()
+                    .add_piece(&key, amount, &snapshot.path.to_string_lossy())?;
+            daemon.store.lock().unwrap().update(|data| {
+                data.pieces.insert(
+                    key,
+                    PieceRecord {
+                        added_at: store::now(),
+                        comm_p: Some(commitment.comm_p),
+                    },
+                )
+            })?;
+
+            Response::PieceAdd {
+                sector_id: id,
+                comm_p: commitment.comm_p,
+            }
         }
         Request::PieceRead(key) => {
-            let bytes = fil_api::read_piece_from_sealed_sector(&sb.lock().unwrap(), &key)?;
+            if let Some(removal) = daemon.store.lock().unwrap().data().removed.get(&key) {
+                bail!("piece {} is {:?}", key, removal.kind);
+            }
+
+            let bytes = sb.lock().unwrap().read_piece(&key)?;
             Response::PieceRead(bytes)
         }
+        Request::PieceList {
+            prefix,
+            offset,
+            limit,
+            include_removed,
+        } => {
+            let mut pieces = list_pieces(daemon)?;
+            if !include_removed {
+                pieces.retain(|piece| piece.removed.is_none());
+            }
+            if let Some(prefix) = prefix {
+                pieces.retain(|piece| piece.key.starts_with(&prefix));
+            }
+
+            Response::PieceList {
+                total: pieces.len() as u64,
+                pieces: pieces
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(limit as usize)
+                    .collect(),
+            }
+        }
+        Request::PieceInfo(key) => match list_pieces(daemon)?.into_iter().find(|p| p.key == key) {
+            Some(piece) => Response::PieceInfo(piece),
+            None => bail!("unknown piece: {}", key),
+        },
+        Request::PieceExpire(key) => {
+            expire_piece(daemon, key)?;
+            Response::PieceExpire
+        }
     };
 
     Ok(response)
 }
 
+/// Collects all pieces in the sealed and staged sectors, ordered by key.
+fn list_pieces(daemon: &Daemon) -> Result<Vec<PieceInfo>, failure::Error> {
+    let (sealed, staged) = {
+        let sb = daemon.sb.lock().unwrap();
+        (sb.sealed_sectors()?, sb.staged_sectors()?)
+    };
+    let store = daemon.store.lock().unwrap();
+
+    let sealed = sealed
+        .into_iter()
+        .map(|meta| (meta.sector_id, SectorState::Sealed, meta.pieces));
+    let staged = staged.into_iter().filter_map(|meta| {
+        let state = match meta.seal_status {
+            SealStatus::Pending => SectorState::Staged,
+            SealStatus::Sealing => SectorState::Sealing,
+            SealStatus::Failed(err) => SectorState::Failed(err),
+            // Listed with the sealed sectors.
+            SealStatus::Sealed(_) => return None,
+        };
+        Some((meta.sector_id, state, meta.pieces))
+    });
+
+    let mut pieces: Vec<_> = sealed
+        .chain(staged)
+        .flat_map(|(sector_id, state, pieces)| {
+            pieces.into_iter().map(move |piece| PieceInfo {
+                added_at: None,
+                comm_p: None,
+                removed: None,
+                key: piece.piece_key,
+                num_bytes: u64::from(piece.num_bytes),
+                sector_id,
+                sector_state: state.clone(),
+            })
+        })
+        .map(|mut piece| {
+            if let Some(record) = store.data().pieces.get(&piece.key) {
+                piece.added_at = Some(record.added_at);
+                piece.comm_p = record.comm_p;
+            }
+            piece.removed = store.data().removed.get(&piece.key).map(|r| r.kind.clone());
+            piece
+        })
+        .collect();
+    pieces.sort_by(|a, b| a.key.cmp(&b.key));
+
+    Ok(pieces)
+}
+
+fn sector_usage(daemon: &Daemon, piece_size: Option<u64>) -> Result<SectorUsage, failure::Error> {
+    let max_user_bytes = fil_api::get_max_user_bytes_per_staged_sector(daemon.sector_size);
+    let staged = daemon.sb.lock().unwrap().staged_sectors()?;
+    let store = daemon.store.lock().unwrap();
+    let now = store::now();
+
+    let mut sectors: Vec<_> = staged
+        .iter()
+        .filter_map(|meta| {
+            let state = match meta.seal_status {
+                SealStatus::Pending => SectorState::Staged,
+                SealStatus::Sealing => SectorState::Sealing,
+                SealStatus::Failed(ref err) => SectorState::Failed(err.clone()),
+                SealStatus::Sealed(_) => return None,
+            };
+            let used_bytes = policy::used_bytes(meta);
+            let free_bytes = max_user_bytes.saturating_sub(used_bytes);
+            let age = meta
+                .pieces
+                .iter()
+                .filter_map(|p| store.data().pieces.get(&p.piece_key))
+                .map(|p| now.saturating_sub(p.added_at))
+                .max();
+            let fits = match state {
+                SectorState::Staged => piece_size.map(|size| size <= free_bytes),
+                _ => None,
+            };
+
+            Some(StagedSectorUsage {
+                sector_id: meta.sector_id,
+                state,
+                used_bytes,
+                free_bytes,
+                num_pieces: meta.pieces.len() as u64,
+                age,
+                fits,
+            })
+        })
+        .collect();
+    sectors.sort_by_key(|s| s.sector_id);
+
+    let unused_sectors =
+        u64::from(daemon.max_num_staged_sectors).saturating_sub(sectors.len() as u64);
+    let used_bytes = sectors.iter().map(|s| s.used_bytes).sum();
+    let open_free_bytes: u64 = sectors
+        .iter()
+        .filter(|s| match s.state {
+            SectorState::Staged => true,
+            _ => false,
+        })
+        .map(|s| s.free_bytes)
+        .sum();
+    let fits = piece_size.map(|size| {
+        sectors.iter().any(|s| s.fits == Some(true))
+            || (unused_sectors > 0 && size <= max_user_bytes)
+    });
+
+    Ok(SectorUsage {
+        sectors,
+        max_num_staged_sectors: daemon.max_num_staged_sectors,
+        max_user_bytes,
+        used_bytes,
+        free_bytes: open_free_bytes + unused_sectors * max_user_bytes,
+        fits,
+    })
+}
+
+/// Takes a piece in a sealed sector out of reads and listings.
+///
+/// Its bytes stay in the sector, but are never handed out again. Staged pieces can not be
+/// expired, the sector builder has no way to drop their bytes, so they would still be sealed.
+/// Staged sectors therefore never hold expired pieces, and their usage counts every piece.
+fn expire_piece(daemon: &Daemon, key: String) -> Result<(), failure::Error> {
+    let piece = match list_pieces(daemon)?.into_iter().find(|p| p.key == key) {
+        Some(piece) => piece,
+        None => bail!("unknown piece: {}", key),
+    };
+    if let Some(removal) = piece.removed {
+        bail!("piece {} is already {:?}", key, removal);
+    }
+
+    match piece.sector_state {
+        SectorState::Sealed => {}
+        state => bail!(
+            "piece {} can not be expired, sector {} is {:?}, only pieces in sealed sectors can be expired",
+            key,
+            piece.sector_id,
+            state
+        ),
+    }
+
+    daemon.store.lock().unwrap().update(|data| {
+        data.removed.insert(
+            key,
+            Removal {
+                at: store::now(),
+                kind: PieceRemoval::Expired,
+            },
+        )
+    })?;
+
+    Ok(())
+}
+
+/// Writes a backup of the metadata to `path`.
+///
+/// Holding the locks keeps requests and background tasks from changing the metadata, but not
+/// the sealing workers of the sector builder, so `backup::snapshot` reads it until it is stable.
+/// The metadata is read before the sealed sectors are listed, so that the manifest covers every
+/// sealed sector it refers to.
+fn create_backup(daemon: &Daemon, path: &str) -> Result<(), failure::Error> {
+    let sb = daemon.sb.lock().unwrap();
+    let _store = daemon.store.lock().unwrap();
+
+    let metadata = backup::snapshot(&daemon.metadata_dir)?;
+    let sealed_sectors = sb
+        .sealed_sectors()?
+        .into_iter()
+        .map(|meta| {
+            Ok(backup::SealedSectorFile {
+                sector_id: meta.sector_id,
+                len: std::fs::metadata(&meta.sector_access)?.len(),
+                path: meta.sector_access,
+            })
+        })
+        .collect::<Result<_, failure::Error>>()?;
+
+    let manifest = backup::Manifest {
+        version: backup::BACKUP_VERSION,
+        created_at: store::now(),
+        prover_id: hex::encode(&daemon.prover_id[..]),
+        sector_size: daemon.sector_size,
+        sealed_sectors,
+    };
+    let settings = SETTINGS.read().unwrap().clone();
+
+    backup::create(&manifest, &settings, &metadata, path)
+}
+
+/// Checks all sealed sectors, and records the faults found.
+fn check_sectors(daemon: &Daemon, checksums: bool) -> Result<Vec<SectorFault>, failure::Error> {
+    // Don't block the sector builder while reading the sectors.
+    let sealed = daemon.sb.lock().unwrap().sealed_sectors()?;
+
+    let mut faults = Vec::new();
+    for meta in &sealed {
+        if let Some(reason) = integrity::check_sector(meta, daemon.sector_size, checksums)? {
+            faults.push(SectorFault {
+                sector_id: meta.sector_id,
+                reason,
+                detected_at: store::now(),
+            });
+        }
+    }
+
+    daemon
+        .store
+        .lock()
+        .unwrap()
+        .update(|data| data.faults = faults.clone())?;
+
+    Ok(faults)
+}
+
+/// Periodically runs `check_sectors`, if enabled.
+fn run_integrity_checks(daemon: Arc<Daemon>) {
+    loop {
+        let (interval, checksums) = {
+            let cfg = SETTINGS.read().unwrap();
+            (cfg.integrity_check_interval, cfg.integrity_check_checksums)
+        };
+
+        let interval = match interval {
+            Some(interval) => interval,
+            None => {
+                // Check again later, in case it gets enabled.
+                thread::sleep(Duration::from_secs(60));
+                continue;
+            }
+        };
+        thread::sleep(Duration::from_secs(interval.max(1)));
+
+        match check_sectors(&daemon, checksums) {
+            Ok(ref faults) if faults.is_empty() => {}
+            Ok(faults) => {
+                for fault in &faults {
+                    println!("sector {} is faulty: {}", fault.sector_id, fault.reason);
+                }
+            }
+            Err(err) => println!("integrity check failed: {}", err),
+        }
+    }
+}
+
+/// Moves newly sealed sectors to the configured storage paths, every `placement_interval`.
+///
+/// Sectors are sealed into the `sealed_sector_dir`, so they are only placed after sealing.
+fn run_placement(daemon: Arc<Daemon>) {
+    loop {
+        let interval = SETTINGS.read().unwrap().placement_interval;
+        thread::sleep(Duration::from_secs(interval.max(1)));
+
+        if let Err(err) = place_sealed_sectors(&daemon) {
+            println!("placing sealed sectors failed: {}", err);
+        }
+    }
+}
+
+fn place_sealed_sectors(daemon: &Daemon) -> Result<(), failure::Error> {
+    let (storage_paths, placement) = {
+        let cfg = SETTINGS.read().unwrap();
+        (cfg.storage_paths.clone(), cfg.placement.clone())
+    };
+    if storage_paths.is_empty() {
+        return Ok(());
+    }
+
+    let sealed = daemon.sb.lock().unwrap().sealed_sectors()?;
+    let unplaced: Vec<_> = {
+        let store = daemon.store.lock().unwrap();
+        sealed
+            .into_iter()
+            .filter(|meta| !store.data().locations.contains_key(&meta.sector_id))
+            .collect()
+    };
+
+    for meta in unplaced {
+        let candidates = storage_candidates(daemon, &storage_paths)?;
+        let counter = daemon.placement_counter.load(Ordering::SeqCst);
+        let path = match placement::choose(&placement, &candidates, daemon.sector_size, counter) {
+            Some(path) => path,
+            None => bail!("no storage path has room for sector {}", meta.sector_id),
+        };
+
+        move_sector(daemon, &meta, &path)?;
+        daemon.placement_counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    Ok(())
+}
+
+/// Moves a sealed sector to another storage path, refusing while it is part of a PoSt.
+fn move_sector(
+    daemon: &Daemon,
+    meta: &SealedSectorMetadata,
+    to: &str,
+) -> Result<SectorMoved, failure::Error> {
+    let _guard = BusyGuard::moving(daemon, meta.sector_id)?;
+    let from = sector_location(daemon, meta.sector_id);
+
+    placement::relocate(&meta.sector_access, to)?;
+    daemon
+        .store
+        .lock()
+        .unwrap()
+        .update(|data| data.locations.insert(meta.sector_id, to.to_string()))?;
+
+    println!(
+        "moved sealed sector {} from {} to {}",
+        meta.sector_id, from, to
+    );
+
+    Ok(SectorMoved {
+        sector_id: meta.sector_id,
+        from,
+        to: to.to_string(),
+    })
+}
+
+/// The storage path a sealed sector is stored in.
+fn sector_location(daemon: &Daemon, sector_id: u64) -> String {
+    daemon
+        .store
+        .lock()
+        .unwrap()
+        .data()
+        .locations
+        .get(&sector_id)
+        .cloned()
+        .unwrap_or_else(|| daemon.sealed_sector_dir.clone())
+}
+
+/// Moves a single sector, or all sectors stored in a path, to other storage paths.
+fn move_sectors(daemon: &Daemon, what: SectorMove) -> Result<Vec<SectorMoved>, failure::Error> {
+    let (storage_paths, placement) = {
+        let cfg = SETTINGS.read().unwrap();
+        (cfg.storage_paths.clone(), cfg.placement.clone())
+    };
+    let sealed = daemon.sb.lock().unwrap().sealed_sectors()?;
+    let is_storage_path = |path: &str| {
+        storage_paths
+            .iter()
+            .any(|s| Path::new(&s.path) == Path::new(path))
+    };
+
+    match what {
+        SectorMove::Sector { sector_id, to } => {
+            let meta = match sealed.iter().find(|meta| meta.sector_id == sector_id) {
+                Some(meta) => meta,
+                None => bail!("unknown sealed sector: {}", sector_id),
+            };
+            match storage_paths
+                .iter()
+                .find(|s| Path::new(&s.path) == Path::new(&to))
+            {
+                Some(storage) if storage.read_only => bail!("{} is read only", to),
+                Some(_) => {}
+                None => bail!("{} is not a configured storage path", to),
+            }
+
+            Ok(vec![move_sector(daemon, meta, &to)?])
+        }
+        SectorMove::Drain { from } => {
+            if !is_storage_path(&from) && Path::new(&from) != Path::new(&daemon.sealed_sector_dir) {
+                bail!("{} is not a configured storage path", from);
+            }
+
+            // Never place sectors on the path being drained.
+            let storage_paths: Vec<_> = storage_paths
+                .into_iter()
+                .filter(|s| Path::new(&s.path) != Path::new(&from))
+                .collect();
+
+            let mut moved = Vec::new();
+            for meta in &sealed {
+                if Path::new(&sector_location(daemon, meta.sector_id)) != Path::new(&from) {
+                    continue;
+                }
+
+                let candidates = storage_candidates(daemon, &storage_paths)?;
+                let counter = daemon.placement_counter.fetch_add(1, Ordering::SeqCst);
+                let to =
+                    match placement::choose(&placement, &candidates, daemon.sector_size, counter) {
+                        Some(to) => to,
+                        None => bail!("no storage path has room for sector {}", meta.sector_id),
+                    };
+
+                moved.push(move_sector(daemon, meta, &to)?);
+            }
+
+            Ok(moved)
+        }
+    }
+}
+
+fn storage_candidates(
+    daemon: &Daemon,
+    storage_paths: &[StoragePath],
+) -> Result<Vec<placement::Candidate>, failure::Error> {
+    let store = daemon.store.lock().unwrap();
+
+    storage_paths
+        .iter()
+        .map(|storage| {
+            let placed = store
+                .data()
+                .locations
+                .values()
+                .filter(|path| Path::new(path) == Path::new(&storage.path))
+                .count() as u64;
+            placement::Candidate::new(storage, placed * daemon.sector_size)
+        })
+        .collect()
+}
+
+/// Periodically applies the configured `SealingPolicy`.
+///
+/// The settings are read again on every round, so changes apply without a restart.
+fn run_sealing_policy(daemon: Arc<Daemon>) {
+    loop {
+        let policy = SETTINGS.read().unwrap().sealing.clone();
+        thread::sleep(Duration::from_secs(policy.interval.max(1)));
+
+        if !policy.is_enabled() {
+            continue;
+        }
+
+        match apply_sealing_policy(&daemon, &policy) {
+            Ok(decision) => {
+                if !decision.due.is_empty() {
+                    println!("sealing policy: {}", decision.reason);
+                }
+                *daemon.last_policy_decision.lock().unwrap() = Some(decision);
+            }
+            Err(err) => println!("sealing policy failed: {}", err),
+        }
+    }
+}
+
+fn apply_sealing_policy(
+    daemon: &Daemon,
+    policy: &crate::settings::SealingPolicy,
+) -> Result<PolicyDecision, failure::Error> {
+    let sb = daemon.sb.lock().unwrap();
+    let staged = sb.staged_sectors()?;
+    let decision = policy::evaluate(
+        policy,
+        &staged,
+        &daemon.store.lock().unwrap().data().pieces,
+        fil_api::get_max_user_bytes_per_staged_sector(daemon.sector_size),
+        store::now(),
+    )?;
+
+    if decision.seal {
+        ensure_seal_space(daemon, decision.started.len() as u64)?;
+        sb.seal_all_staged()?;
+    }
+
+    Ok(decision)
+}
+
+/// The ids of the staged sectors which would be sealed by `seal_all_staged_sectors`.
+fn open_sectors(staged: &[StagedSectorMetadata]) -> Vec<u64> {
+    staged
+        .iter()
+        .filter(|meta| match meta.seal_status {
+            SealStatus::Pending => !meta.pieces.is_empty(),
+            _ => false,
+        })
+        .map(|meta| meta.sector_id)
+        .collect()
+}
+
+/// Every sealed sector takes a full sector worth of space in the `sealed_sector_dir`.
+fn ensure_seal_space(daemon: &Daemon, num_sectors: u64) -> Result<(), failure::Error> {
+    space::ensure_available(&daemon.sealed_sector_dir, num_sectors * daemon.sector_size)
+}
+
+fn disk_status(daemon: &Daemon) -> Result<Vec<DiskStatus>, failure::Error> {
+    let (warning, critical) = {
+        let cfg = SETTINGS.read().unwrap();
+        (cfg.disk_warning_percent, cfg.disk_critical_percent)
+    };
+
+    [
+        &daemon.staged_sector_dir,
+        &daemon.sealed_sector_dir,
+        &daemon.metadata_dir,
+    ]
+    .iter()
+    .map(|dir| space::disk_status(dir, warning, critical))
+    .collect()
+}
+
+/// Verifies the seal of one of our own sectors, using the commitments and proof stored
+/// in its `SealedSectorMetadata`.
+///
+/// Only missing metadata is attributed to a field. The proofs can not tell which of their inputs
+/// is wrong, so a proof which does not verify names none.
+fn verify_sealed_sector(
+    daemon: &Daemon,
+    sector_id: u64,
+) -> Result<SealVerifyResult, failure::Error> {
+    let invalid = |field: &str, reason: String| SealVerifyResult::Invalid {
+        field: Some(field.into()),
+        reason,
+    };
+
+    let sealed = daemon.sb.lock().unwrap().sealed_sectors()?;
+    let meta = match sealed.into_iter().find(|meta| meta.sector_id == sector_id) {
+        Some(meta) => meta,
+        None => {
+            let status = daemon.sb.lock().unwrap().seal_status(sector_id)?;
+            return Ok(invalid(
+                "seal_status",
+                format!("sector is not sealed: {:?}", status),
+            ));
+        }
+    };
+
+    if meta.proof.is_empty() {
+        return Ok(invalid("proof", "no proof stored".into()));
+    }
+    for (field, comm) in &[
+        ("comm_r", meta.comm_r),
+        ("comm_d", meta.comm_d),
+        ("comm_r_star", meta.comm_r_star),
+    ] {
+        if comm.iter().all(|b| *b == 0) {
+            return Ok(invalid(field, "no commitment stored".into()));
+        }
+    }
+
+    let valid = daemon.sb.lock().unwrap().verify_seal(
+        daemon.sector_size,
+        meta.comm_r,
+        meta.comm_d,
+        meta.comm_r_star,
+        &daemon.prover_id,
+        &sector_id_as_bytes(sector_id),
+        meta.proof,
+    );
+
+    let result = match valid {
+        Ok(true) => SealVerifyResult::Valid,
+        Ok(false) => SealVerifyResult::Invalid {
+            field: None,
+            reason: "the proof does not verify against the stored commitments".into(),
+        },
+        Err(err) => SealVerifyResult::Invalid {
+            field: None,
+            reason: format!("verification failed: {}", err),
+        },
+    };
+
+    Ok(result)
+}
+
+/// Sector sizes from requests are checked before they reach the proofs. Sectors are powers of
+/// two of at least 128 bytes, and the limit of 1 PiB keeps the size arithmetic from overflowing.
+fn ensure_sector_size(sector_size: u64) -> Result<(), failure::Error> {
+    if sector_size < 128 || !sector_size.is_power_of_two() || sector_size > 1 << 50 {
+        bail!("invalid sector size: {}", sector_size);
+    }
+    Ok(())
+}
+
+/// A copy of the first bytes of a piece in the `staged_sector_dir`, removed on drop.
+struct PieceSnapshot {
+    path: PathBuf,
+}
+
+impl PieceSnapshot {
+    fn create(daemon: &Daemon, path: &str, amount: u64) -> Result<Self, failure::Error> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let snapshot = PieceSnapshot {
+            path: Path::new(&daemon.staged_sector_dir)
+                .join(format!(".piece-{}", NEXT.fetch_add(1, Ordering::SeqCst))),
+        };
+
+        let mut file = File::open(path)?.take(amount);
+        let copied = io::copy(&mut file, &mut File::create(&snapshot.path)?)?;
+        if copied < amount {
+            bail!("{} has less than {} bytes", path, amount);
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl Drop for PieceSnapshot {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
 fn get_file_size(path: &str) -> Result<u64, failure::Error> {
     let data = std::fs::metadata(path)?;
 
     Ok(data.len())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::FakeBackend;
+
+    fn daemon(dir: &Path) -> Daemon {
+        let mut cfg = Settings::default();
+        cfg.metadata_dir = dir.join("meta").to_string_lossy().into();
+        cfg.sealed_sector_dir = dir.join("sealed").to_string_lossy().into();
+        cfg.staged_sector_dir = dir.join("staged").to_string_lossy().into();
+        std::fs::create_dir_all(&cfg.staged_sector_dir).unwrap();
+
+        Daemon::new(&cfg, Some([1; 31]), 1024, |cfg, prover_id| {
+            let backend = FakeBackend::new(
+                1024,
+                cfg.post_partitions,
+                cfg.max_num_staged_sectors,
+                0,
+                prover_id,
+                &cfg.sealed_sector_dir,
+            )?;
+            Ok(Box::new(backend))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn seal_and_prove() {
+        let dir = tempfile::tempdir().unwrap();
+        let daemon = daemon(dir.path());
+        let path = dir.path().join("piece");
+        std::fs::write(&path, vec![7u8; 100]).unwrap();
+
+        let add = Request::PieceAdd {
+            key: "a".into(),
+            amount: None,
+            path: path.to_string_lossy().into(),
+        };
+        match respond(add, &daemon).unwrap() {
+            Response::PieceAdd { sector_id, .. } => assert_eq!(sector_id, 1),
+            res => panic!("unexpected response: {:?}", res),
+        }
+        assert!(respond(Request::PieceRead("a".into()), &daemon).is_err());
+
+        respond(Request::SealAllStaged, &daemon).unwrap();
+        match respond(Request::PieceRead("a".into()), &daemon).unwrap() {
+            Response::PieceRead(bytes) => assert_eq!(bytes, vec![7u8; 100]),
+            res => panic!("unexpected response: {:?}", res),
+        }
+        match respond(Request::SealVerifySector(1), &daemon).unwrap() {
+            Response::SealVerifySector(SealVerifyResult::Valid) => {}
+            res => panic!("unexpected response: {:?}", res),
+        }
+
+        let post = Request::PostGenerateSealed {
+            sectors: SectorFilter::All,
+            challenge_seed: [3; 32],
+        };
+        let verify = match respond(post, &daemon).unwrap() {
+            Response::PostGenerateSealed {
+                sector_size,
+                proof_partitions,
+                sector_ids,
+                comm_rs,
+                challenge_seed,
+                proofs,
+                faults,
+                ..
+            } => {
+                assert_eq!(sector_ids, vec![1]);
+                Request::PostVerify {
+                    sector_size,
+                    proof_partitions,
+                    comm_rs,
+                    challenge_seed,
+                    proofs,
+                    faults,
+                }
+            }
+            res => panic!("unexpected response: {:?}", res),
+        };
+        match respond(verify, &daemon).unwrap() {
+            Response::PostVerify(valid) => assert!(valid),
+            res => panic!("unexpected response: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unknown_piece() {
+        let dir = tempfile::tempdir().unwrap();
+        let daemon = daemon(dir.path());
+
+        assert!(respond(Request::PieceInfo("missing".into()), &daemon).is_err());
+    }
+
+    #[test]
+    fn invalid_sector_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let daemon = daemon(dir.path());
+
+        for size in &[0, 1000, u64::max_value(), 1 << 63] {
+            assert!(respond(Request::SectorSize(*size), &daemon).is_err());
+        }
+        match respond(Request::SectorSize(1024), &daemon).unwrap() {
+            Response::SectorSize(size) => assert_eq!(size, 1016),
+            res => panic!("unexpected response: {:?}", res),
+        }
+    }
+}
@@ -1,15 +1,52 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Write};
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 
+use config::Source as _;
+use failure::{Error, Fail};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
+use crate::api::ConfigReload;
+
+/// The config file loaded by default, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "filbase.config.toml";
+
+/// Prefix of the environment variables overriding settings, e.g. `FILBASE_PORT`. Nested values
+/// are separated by `__`, e.g. `FILBASE_SEALING__FILL_PERCENT`. Variables not naming a setting are
+/// ignored.
+pub const ENV_PREFIX: &str = "FILBASE";
+
 lazy_static! {
-    pub static ref SETTINGS: Arc<RwLock<Settings>> =
-        Arc::new(RwLock::new(Settings::new().expect("invalid configuration")));
+    /// The active settings, loaded by `Settings::init`.
+    pub static ref SETTINGS: Arc<RwLock<Settings>> = Arc::new(RwLock::new(Settings::default()));
+    /// The config file passed to `Settings::init`, used again on reload.
+    static ref CONFIG_PATH: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// Settings which are only read when the daemon starts, and are not changed by a reload.
+pub const RESTART_ONLY: &[&str] = &[
+    "host",
+    "port",
+    "porep_partitions",
+    "post_partitions",
+    "metadata_dir",
+    "sealed_sector_dir",
+    "staged_sector_dir",
+    "max_num_staged_sectors",
+];
+
+/// A setting with an invalid value.
+#[derive(Debug, Fail)]
+#[fail(display = "invalid configuration: {}: {}", key, reason)]
+pub struct InvalidSetting {
+    pub key: String,
+    pub reason: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(default)]
+#[serde(default, deny_unknown_fields)]
 pub struct Settings {
     pub host: String,
     pub port: usize,
@@ -18,7 +55,118 @@ pub struct Settings {
     pub metadata_dir: String,
     pub sealed_sector_dir: String,
     pub staged_sector_dir: String,
+    /// Where sealed sectors are stored. If empty, they stay in the `sealed_sector_dir`.
+    ///
+    /// The sector builder only knows the `sealed_sector_dir`, so sectors are always sealed there
+    /// and placed on a storage path afterwards, leaving a symlink behind. A full
+    /// `sealed_sector_dir` therefore still blocks sealing, even if the storage paths have room.
+    pub storage_paths: Vec<StoragePath>,
+    /// How new sealed sectors are distributed over the `storage_paths`.
+    pub placement: Placement,
+    /// How often newly sealed sectors are moved to the `storage_paths`, in seconds.
+    ///
+    /// Sectors are sealed into the `sealed_sector_dir` and only moved afterwards, staged sectors
+    /// always stay in the `staged_sector_dir`.
+    pub placement_interval: u64,
     pub max_num_staged_sectors: u8,
+    /// Warn when the free space of a sector directory drops below this percentage.
+    pub disk_warning_percent: u8,
+    /// Report the free space of a sector directory as critical below this percentage.
+    pub disk_critical_percent: u8,
+    /// Check the sealed sectors for faults every this many seconds, disabled if not set.
+    pub integrity_check_interval: Option<u64>,
+    /// Whether the periodic integrity check also compares the sealed sectors against the checksums
+    /// taken when they were sealed.
+    pub integrity_check_checksums: bool,
+    pub sealing: SealingPolicy,
+    /// Directories which are watched for new pieces.
+    pub inboxes: Vec<Inbox>,
+    /// How often the inboxes are checked for new files, in seconds.
+    pub inbox_interval: u64,
+}
+
+/// A directory sealed sectors can be stored in.
+///
+/// Sealed sectors are moved here after sealing, a symlink in the `sealed_sector_dir` keeps them
+/// available to the sector builder.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StoragePath {
+    pub path: String,
+    /// The relative share of new sectors placed on this path.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// The maximum number of bytes of sealed sectors to place on this path.
+    #[serde(default)]
+    pub capacity: Option<u64>,
+    /// Keep the sectors stored here, but don't place new ones.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// Place new sectors on the path with the most free space, scaled by its weight.
+    FreeSpace,
+    /// Cycle through the paths, each getting as many sectors in a row as its weight.
+    RoundRobin,
+}
+
+/// A directory watched for new pieces.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Inbox {
+    pub dir: String,
+    /// Where files are moved after they were added.
+    pub done_dir: String,
+    /// Where files are moved when adding them failed.
+    pub failed_dir: String,
+    /// Read the key of `file` from `file.key`, instead of using the file name.
+    #[serde(default)]
+    pub key_from_sidecar: bool,
+}
+
+/// When the daemon seals staged sectors on its own.
+///
+/// A staged sector is due for sealing as soon as any of the configured conditions is met. The
+/// policy is disabled when none are configured.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SealingPolicy {
+    /// Seal once a sector is filled to this percentage of its capacity.
+    pub fill_percent: Option<u8>,
+    /// Seal once the oldest piece in a sector is older than this, in seconds.
+    pub max_piece_age: Option<u64>,
+    /// Seal all sectors holding pieces during these windows, given in UTC as `HH:MM-HH:MM`.
+    pub windows: Vec<String>,
+    /// The maximum number of sectors sealing at the same time. As all open sectors are sealed
+    /// together, more than this are sealed when nothing else is sealing.
+    pub max_concurrent_seals: usize,
+    /// How often the policy is evaluated, in seconds.
+    pub interval: u64,
+}
+
+impl Default for SealingPolicy {
+    fn default() -> Self {
+        SealingPolicy {
+            fill_percent: None,
+            max_piece_age: None,
+            windows: Vec::new(),
+            max_concurrent_seals: 2,
+            interval: 60,
+        }
+    }
+}
+
+impl SealingPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.fill_percent.is_some() || self.max_piece_age.is_some() || !self.windows.is_empty()
+    }
 }
 
 impl Default for Settings {
@@ -31,28 +179,553 @@ impl Default for Settings {
             metadata_dir: "meta".into(),
             sealed_sector_dir: "sealed".into(),
             staged_sector_dir: "staged".into(),
+            storage_paths: Vec::new(),
+            placement: Placement::FreeSpace,
+            placement_interval: 30,
             max_num_staged_sectors: 10,
+            disk_warning_percent: 10,
+            disk_critical_percent: 5,
+            integrity_check_interval: None,
+            integrity_check_checksums: false,
+            sealing: SealingPolicy::default(),
+            inboxes: Vec::new(),
+            inbox_interval: 10,
         }
     }
 }
 
 impl Settings {
-    fn new() -> Result<Settings, config::ConfigError> {
-        let mut s = config::Config::new();
-        s.merge(config::File::with_name("filbase.config.toml").required(false))?;
+    /// Loads the settings and makes them the active `SETTINGS`. They are not validated yet, so
+    /// that command line overrides can be applied first.
+    pub fn init(path: Option<&str>) -> Result<(), Error> {
+        let settings = Settings::load(path)?;
+        *SETTINGS.write().unwrap() = settings;
+        *CONFIG_PATH.write().unwrap() = path.map(Into::into);
 
-        s.try_into()
+        Ok(())
     }
 
-    pub fn load_config<S: AsRef<str>>(path: S) {
-        let mut s = config::Config::new();
-        s.merge(config::File::with_name(path.as_ref()).required(true))
-            .unwrap();
+    /// Loads the settings again from the same config file and the environment. Changes to the
+    /// `RESTART_ONLY` settings are ignored.
+    pub fn reload() -> Result<ConfigReload, Error> {
+        let path = CONFIG_PATH.read().unwrap().clone();
+        let loaded = Settings::load(path.as_ref().map(String::as_str))?;
+        let mut new = loaded.clone();
+
+        let mut settings = SETTINGS.write().unwrap();
+        let old = settings.clone();
+        new.host = old.host.clone();
+        new.port = old.port;
+        new.porep_partitions = old.porep_partitions;
+        new.post_partitions = old.post_partitions;
+        new.metadata_dir = old.metadata_dir.clone();
+        new.sealed_sector_dir = old.sealed_sector_dir.clone();
+        new.staged_sector_dir = old.staged_sector_dir.clone();
+        new.max_num_staged_sectors = old.max_num_staged_sectors;
+        new.validate()?;
 
-        *SETTINGS.clone().write().unwrap() = s.try_into().unwrap();
+        let reload = ConfigReload {
+            applied: changed(&old, &new)?.into_iter().collect(),
+            ignored: changed(&new, &loaded)?.into_iter().collect(),
+        };
+        *settings = new;
+
+        Ok(reload)
+    }
+
+    /// Loads the settings from the defaults, the config file and the environment, in that order.
+    ///
+    /// Without an explicit `path`, `filbase.config.toml` is used if it exists.
+    pub fn load(path: Option<&str>) -> Result<Settings, Error> {
+        let mut s = config_file(path)?;
+        for (key, value) in env()? {
+            s.set(&key, value)?;
+        }
+
+        Ok(s.try_into()?)
+    }
+
+    /// Loads and validates a config file on its own, ignoring the environment.
+    pub fn check(path: &str) -> Result<Settings, Error> {
+        let settings: Settings = config_file(Some(path))?.try_into()?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    /// Lists every setting with its value and where it was set. `cli` are the keys overridden
+    /// on the command line.
+    pub fn sources(
+        &self,
+        path: Option<&str>,
+        cli: &[&str],
+    ) -> Result<Vec<(String, toml::Value, Source)>, Error> {
+        let path = match path {
+            Some(path) => Some(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Some(DEFAULT_CONFIG_FILE),
+            None => None,
+        };
+        let mut file = HashMap::new();
+        if path.is_some() {
+            flatten_config("", config_file(path)?.try_into()?, &mut file);
+        }
+        let env = env()?;
+
+        let mut values = Vec::new();
+        flatten_toml("", toml::Value::try_from(self)?, &mut values);
+
+        Ok(values
+            .into_iter()
+            .map(|(key, value)| {
+                let source = if cli.contains(&key.as_str()) {
+                    Source::Cli
+                } else if env.contains_key(&key) {
+                    Source::Env(format!(
+                        "{}_{}",
+                        ENV_PREFIX,
+                        key.to_uppercase().replace('.', "__")
+                    ))
+                } else if let (Some(path), true) = (path, file.contains_key(&key)) {
+                    Source::File(path.into())
+                } else {
+                    Source::Default
+                };
+                (key, value, source)
+            })
+            .collect())
+    }
+
+    /// Checks that all values are in range.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |key: &str, reason: String| -> Result<(), Error> {
+            Err(InvalidSetting {
+                key: key.into(),
+                reason,
+            }
+            .into())
+        };
+
+        if self.host.is_empty() {
+            invalid("host", "must not be empty".into())?;
+        }
+        if self.port == 0 || self.port > 65535 {
+            invalid(
+                "port",
+                format!("must be between 1 and 65535, got {}", self.port),
+            )?;
+        }
+        if self.porep_partitions == 0 {
+            invalid("porep_partitions", "must be at least 1".into())?;
+        }
+        if self.post_partitions == 0 {
+            invalid("post_partitions", "must be at least 1".into())?;
+        }
+        if self.max_num_staged_sectors == 0 {
+            invalid("max_num_staged_sectors", "must be at least 1".into())?;
+        }
+        if self.disk_warning_percent > 100 {
+            invalid("disk_warning_percent", "must be at most 100".into())?;
+        }
+        if self.disk_critical_percent > self.disk_warning_percent {
+            invalid(
+                "disk_critical_percent",
+                "must not be above disk_warning_percent".into(),
+            )?;
+        }
+        if self.sealing.fill_percent.map(|p| p > 100).unwrap_or(false) {
+            invalid("sealing.fill_percent", "must be at most 100".into())?;
+        }
+        if self.sealing.max_concurrent_seals == 0 {
+            invalid("sealing.max_concurrent_seals", "must be at least 1".into())?;
+        }
+        for window in &self.sealing.windows {
+            if let Err(err) = crate::policy::parse_window(window) {
+                invalid("sealing.windows", err.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that all configured directories exist, as needed to run the daemon.
+    pub fn validate_dirs(&self) -> Result<(), Error> {
+        let dirs = [
+            ("metadata_dir", &self.metadata_dir),
+            ("sealed_sector_dir", &self.sealed_sector_dir),
+            ("staged_sector_dir", &self.staged_sector_dir),
+        ];
+        let storage_paths = self
+            .storage_paths
+            .iter()
+            .map(|s| ("storage_paths.path", &s.path));
+        let inboxes = self.inboxes.iter().flat_map(|inbox| {
+            vec![
+                ("inboxes.dir", &inbox.dir),
+                ("inboxes.done_dir", &inbox.done_dir),
+                ("inboxes.failed_dir", &inbox.failed_dir),
+            ]
+        });
+
+        for (key, dir) in dirs.iter().cloned().chain(storage_paths).chain(inboxes) {
+            // Missing directories are created, as long as their parent exists.
+            let path = Path::new(dir);
+            let parent = match path.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            if path.exists() && !path.is_dir() {
+                return Err(InvalidSetting {
+                    key: key.into(),
+                    reason: format!("{} is not a directory", dir),
+                }
+                .into());
+            }
+            if !path.exists() && !parent.is_dir() {
+                return Err(InvalidSetting {
+                    key: key.into(),
+                    reason: format!("{} does not exist", parent.display()),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
     }
 
     pub fn server(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
 }
+
+/// Where the value of a setting comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(String),
+    Env(String),
+    Cli,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+fn config_file(path: Option<&str>) -> Result<config::Config, Error> {
+    let mut s = config::Config::new();
+    match path {
+        Some(path) => s.merge(config::File::with_name(path).required(true))?,
+        None => s.merge(config::File::with_name(DEFAULT_CONFIG_FILE).required(false))?,
+    };
+
+    Ok(s)
+}
+
+/// The settings from the environment, by their dotted key.
+///
+/// Only variables naming a known setting are taken, so that unrelated `FILBASE_*` variables do
+/// not trip `deny_unknown_fields`, which is meant for typos in config files.
+fn env() -> Result<HashMap<String, config::Value>, Error> {
+    let known = setting_keys()?;
+    let env = config::Environment::with_prefix(ENV_PREFIX)
+        .separator("__")
+        .collect()?;
+
+    Ok(env
+        .into_iter()
+        .filter(|(key, _)| known.contains(key))
+        .collect())
+}
+
+/// The dotted keys of all settings, including tables and optional settings without a default.
+fn setting_keys() -> Result<BTreeSet<String>, Error> {
+    // Unlike TOML, JSON keeps the settings which are `None`.
+    let mut keys = BTreeSet::new();
+    collect_keys("", &serde_json::to_value(Settings::default())?, &mut keys);
+
+    Ok(keys)
+}
+
+fn collect_keys(prefix: &str, value: &serde_json::Value, out: &mut BTreeSet<String>) {
+    if let serde_json::Value::Object(map) = value {
+        for (key, value) in map {
+            let key = format!("{}{}", prefix, key);
+            collect_keys(&format!("{}.", key), value, out);
+            out.insert(key);
+        }
+    }
+}
+
+fn flatten_config(
+    prefix: &str,
+    values: HashMap<String, config::Value>,
+    out: &mut HashMap<String, config::Value>,
+) {
+    for (key, value) in values {
+        let key = format!("{}{}", prefix, key);
+        match value.clone().into_table() {
+            Ok(table) => flatten_config(&format!("{}.", key), table, out),
+            Err(_) => {
+                out.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The keys of the settings that differ.
+fn changed(a: &Settings, b: &Settings) -> Result<BTreeSet<String>, Error> {
+    let mut a_values = Vec::new();
+    flatten_toml("", toml::Value::try_from(a)?, &mut a_values);
+    let mut b_values = Vec::new();
+    flatten_toml("", toml::Value::try_from(b)?, &mut b_values);
+
+    let a_values: HashMap<_, _> = a_values.into_iter().collect();
+    let b_values: HashMap<_, _> = b_values.into_iter().collect();
+
+    Ok(a_values
+        .keys()
+        .chain(b_values.keys())
+        .filter(|key| a_values.get(*key) != b_values.get(*key))
+        .cloned()
+        .collect())
+}
+
+fn flatten_toml(prefix: &str, value: toml::Value, out: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten_toml(&format!("{}{}.", prefix, key), value, out);
+            }
+        }
+        value => out.push((prefix.trim_end_matches('.').into(), value)),
+    }
+}
+
+/// The documented settings, in the order they are written by `template`. Settings without a
+/// default are written commented out, with the given example. Every setting must be listed,
+/// except for the lists of tables in `TEMPLATE_TABLES`.
+const TEMPLATE_FIELDS: &[(&str, &str, Option<&str>)] = &[
+    (
+        "host",
+        "The host the daemon listens on and the client connects to.",
+        None,
+    ),
+    (
+        "port",
+        "The port the daemon listens on and the client connects to.",
+        None,
+    ),
+    ("porep_partitions", "Partitions of the seal proofs.", None),
+    ("post_partitions", "Partitions of the PoSt proofs.", None),
+    (
+        "metadata_dir",
+        "Where the sector builder keeps its metadata.",
+        None,
+    ),
+    ("sealed_sector_dir", "Where sealed sectors are written.", None),
+    ("staged_sector_dir", "Where pieces are staged before sealing.", None),
+    (
+        "max_num_staged_sectors",
+        "How many staged sectors can be open at the same time.",
+        None,
+    ),
+    (
+        "disk_warning_percent",
+        "Warn in the status, when the free space of the sector directories drops below this percentage.",
+        None,
+    ),
+    (
+        "disk_critical_percent",
+        "Report the free space of the sector directories as critical below this percentage.",
+        None,
+    ),
+    (
+        "integrity_check_interval",
+        "Check the sealed sectors for missing or damaged files every this many seconds.",
+        Some("3600"),
+    ),
+    (
+        "integrity_check_checksums",
+        "Also compare every sealed sector against the checksum taken when it was sealed, this reads all sealed data.",
+        None,
+    ),
+    (
+        "placement",
+        "How new sealed sectors are distributed over the storage paths, \"free-space\" or \"round-robin\".",
+        None,
+    ),
+    (
+        "placement_interval",
+        "How often newly sealed sectors are moved to the storage paths, in seconds. Sectors are\nsealed in the sealed_sector_dir and moved afterwards, staged sectors are never moved.",
+        None,
+    ),
+    (
+        "inbox_interval",
+        "How often the inboxes are checked for new files, in seconds.",
+        None,
+    ),
+    (
+        "sealing",
+        "Automatic sealing of staged sectors. A sector is sealed as soon as any\nof the configured conditions is met, the policy is disabled if none are set.",
+        None,
+    ),
+    (
+        "sealing.fill_percent",
+        "Seal once a sector is filled to this percentage.",
+        Some("90"),
+    ),
+    (
+        "sealing.max_piece_age",
+        "Seal once the oldest piece in a sector is older than this, in seconds.",
+        Some("86400"),
+    ),
+    (
+        "sealing.windows",
+        "Seal all sectors holding pieces during these windows (UTC), e.g. [\"22:00-06:00\"].",
+        None,
+    ),
+    (
+        "sealing.max_concurrent_seals",
+        "How many sectors are sealed at the same time. All open sectors are sealed together,\nso the policy waits while sealing them all would exceed this.",
+        None,
+    ),
+    (
+        "sealing.interval",
+        "How often the policy is checked, in seconds.",
+        None,
+    ),
+];
+
+/// The lists of tables, which are empty by default.
+const TEMPLATE_TABLES: &str = r#"
+# Directories watched for new pieces. Files are added once they stopped changing,
+# using the file name as key, or the content of `<file>.key` if `key_from_sidecar` is set.
+# [[inboxes]]
+# dir = "inbox"
+# done_dir = "inbox-done"
+# failed_dir = "inbox-failed"
+# key_from_sidecar = false
+
+# Paths sealed sectors are moved to after sealing. Without any, they stay in `sealed_sector_dir`.
+# Sealing always writes to `sealed_sector_dir`, so it needs room for the sectors being sealed.
+# [[storage_paths]]
+# path = "/mnt/disk1/sealed"
+# weight = 1
+# capacity = 1099511627776
+# read_only = false
+"#;
+
+/// A commented config file with the default settings.
+pub fn template() -> Result<String, Error> {
+    let defaults = toml::Value::try_from(Settings::default())?;
+    let mut out = String::new();
+
+    for (i, &(key, doc, example)) in TEMPLATE_FIELDS.iter().enumerate() {
+        if i > 0 && !doc.is_empty() {
+            out.push('\n');
+        }
+        for line in doc.lines() {
+            writeln!(out, "# {}", line)?;
+        }
+
+        let name = key.rsplit('.').next().unwrap_or(key);
+        let value = key
+            .split('.')
+            .try_fold(&defaults, |value, part| value.get(part));
+        match (value, example) {
+            (Some(toml::Value::Table(_)), _) => writeln!(out, "[{}]", key)?,
+            (Some(value), _) => writeln!(out, "{} = {}", name, value)?,
+            (None, Some(example)) => writeln!(out, "# {} = {}", name, example)?,
+            (None, None) => writeln!(out, "# {} =", name)?,
+        }
+    }
+    out.push_str(TEMPLATE_TABLES);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_key(settings: &Settings) -> String {
+        let err = settings.validate().unwrap_err();
+        err.downcast::<InvalidSetting>().unwrap().key
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Settings::default().validate().is_ok());
+
+        let mut settings = Settings::default();
+        settings.port = 70000;
+        assert_eq!(invalid_key(&settings), "port");
+
+        let mut settings = Settings::default();
+        settings.post_partitions = 0;
+        assert_eq!(invalid_key(&settings), "post_partitions");
+
+        let mut settings = Settings::default();
+        settings.sealing.windows = vec!["8-17".into()];
+        assert_eq!(invalid_key(&settings), "sealing.windows");
+    }
+
+    #[test]
+    fn template_matches_defaults() {
+        let settings: Settings = toml::from_str(&template().unwrap()).unwrap();
+        let defaults = Settings::default();
+
+        assert_eq!(settings.port, defaults.port);
+        assert_eq!(settings.staged_sector_dir, defaults.staged_sector_dir);
+        assert_eq!(
+            settings.sealing.max_concurrent_seals,
+            defaults.sealing.max_concurrent_seals
+        );
+        assert!(settings.integrity_check_interval.is_none());
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn changed_keys() {
+        let old = Settings::default();
+        let mut new = Settings::default();
+        new.sealing.fill_percent = Some(90);
+        new.port = 9999;
+
+        let changed: Vec<_> = changed(&old, &new).unwrap().into_iter().collect();
+        assert_eq!(changed, vec!["port", "sealing.fill_percent"]);
+    }
+
+    #[test]
+    fn unrelated_env() {
+        std::env::set_var("FILBASE_NOT_A_SETTING", "1");
+        assert!(!env().unwrap().contains_key("not_a_setting"));
+        assert!(Settings::load(None).is_ok());
+    }
+
+    #[test]
+    fn template_covers_settings() {
+        let mut documented: BTreeSet<_> = TEMPLATE_FIELDS
+            .iter()
+            .map(|&(key, _, _)| key.to_string())
+            .collect();
+        documented.insert("inboxes".into());
+        documented.insert("storage_paths".into());
+
+        assert_eq!(setting_keys().unwrap(), documented);
+    }
+
+    #[test]
+    fn example_is_template() {
+        assert_eq!(
+            include_str!("../filbase.config.example.toml"),
+            template().unwrap()
+        );
+    }
+}
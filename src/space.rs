@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use failure::Error;
+
+use crate::api::{DiskLevel, DiskStatus, InsufficientSpace};
+
+/// Fails with `InsufficientSpace` if less than `required` bytes are available at `path`.
+pub fn ensure_available<P: AsRef<Path>>(path: P, required: u64) -> Result<(), Error> {
+    let available = fs2::available_space(path.as_ref())?;
+    if available < required {
+        return Err(InsufficientSpace {
+            path: path.as_ref().display().to_string(),
+            required,
+            available,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Reports the free space at `path`, compared against the warning and critical thresholds, given
+/// in percent of the total space.
+pub fn disk_status<P: AsRef<Path>>(
+    path: P,
+    warning_percent: u8,
+    critical_percent: u8,
+) -> Result<DiskStatus, Error> {
+    let available = fs2::available_space(path.as_ref())?;
+    let total = fs2::total_space(path.as_ref())?;
+
+    let below = |percent: u8| available * 100 < total * u64::from(percent);
+    let level = if below(critical_percent) {
+        DiskLevel::Critical
+    } else if below(warning_percent) {
+        DiskLevel::Warning
+    } else {
+        DiskLevel::Ok
+    };
+
+    Ok(DiskStatus {
+        path: path.as_ref().display().to_string(),
+        available,
+        total,
+        level,
+    })
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::{PieceRemoval, SectorFault};
+
+/// The name of the file, inside the `metadata_dir`, the store is persisted in.
+const STORE_FILE: &str = "filbase.cbor";
+
+/// State that filbase keeps about sectors and pieces, in addition to what the sector
+/// builder tracks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreData {
+    /// The prover id the sector builder was first started with.
+    pub prover_id: Option<[u8; 31]>,
+    /// Pieces added through this daemon, by key.
+    pub pieces: BTreeMap<String, PieceRecord>,
+    /// Pieces which were expired, by key.
+    pub removed: BTreeMap<String, Removal>,
+    /// The faulty sealed sectors, as found by the last integrity check.
+    pub faults: Vec<SectorFault>,
+    /// The storage path of each sealed sector moved out of the `sealed_sector_dir`.
+    pub locations: BTreeMap<u64, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PieceRecord {
+    /// When the piece was added, in seconds since the unix epoch.
+    pub added_at: u64,
+    /// The commitment of the bytes handed to the sector builder. Unknown for pieces added before
+    /// it was recorded.
+    #[serde(default)]
+    pub comm_p: Option<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Removal {
+    /// When the piece was expired, in seconds since the unix epoch.
+    pub at: u64,
+    pub kind: PieceRemoval,
+}
+
+/// Persisted `StoreData`.
+///
+/// Every update is written to a temporary file first, which then replaces the old state, so
+/// that a crash never leaves a partially written store behind.
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    data: StoreData,
+}
+
+impl Store {
+    /// Opens the store in the given directory, starting empty if none exists yet.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, failure::Error> {
+        fs::create_dir_all(dir.as_ref())?;
+        let path = dir.as_ref().join(STORE_FILE);
+
+        let data = if path.exists() {
+            serde_cbor::from_slice(&fs::read(&path)?)?
+        } else {
+            StoreData::default()
+        };
+
+        Ok(Store { path, data })
+    }
+
+    pub fn data(&self) -> &StoreData {
+        &self.data
+    }
+
+    /// Applies `f` to the data and persists the result.
+    pub fn update<F, T>(&mut self, f: F) -> Result<T, failure::Error>
+    where
+        F: FnOnce(&mut StoreData) -> T,
+    {
+        let mut data = self.data.clone();
+        let res = f(&mut data);
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_cbor::to_vec(&data)?)?;
+        fs::rename(&tmp, &self.path)?;
+        self.data = data;
+
+        Ok(res)
+    }
+}
+
+/// The current time, in seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
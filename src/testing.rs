@@ -0,0 +1,163 @@
+//! Runs a daemon on the `FakeBackend`, for tests of the daemon and of crates using it.
+//!
+//! ```no_run
+//! # #![feature(async_await)]
+//! # async fn run() -> Result<(), failure::Error> {
+//! let daemon = filbase::testing::TestDaemon::start().await?;
+//! let client = daemon.client();
+//!
+//! let path = daemon.write_file("piece", &[1; 100])?;
+//! let piece = client.piece_add("piece", None, path.to_string_lossy()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use failure::Error;
+use tempfile::TempDir;
+
+use crate::api::{Request, Response};
+use crate::backend::FakeBackend;
+use crate::server::Server;
+use crate::settings::Settings;
+use crate::{ClientConfig, FilbaseClient};
+
+/// The sector size of the test daemons, holding 1016 bytes of pieces.
+pub const SECTOR_SIZE: u64 = 1024;
+
+/// The prover id of the test daemons.
+pub const PROVER_ID: [u8; 31] = [1; 31];
+
+/// A daemon on an ephemeral port, with its directories in a temporary directory.
+///
+/// The daemon keeps running until the process exits, its directory is removed on drop. The
+/// background tasks, like the sealing policy and the inboxes, are not started, so that daemons
+/// in the same process do not share the global settings.
+pub struct TestDaemon {
+    dir: TempDir,
+    addr: SocketAddr,
+    settings: Settings,
+}
+
+impl TestDaemon {
+    pub async fn start() -> Result<TestDaemon, Error> {
+        TestDaemon::start_with(|_| {}).await
+    }
+
+    /// Starts a daemon with the settings changed by `configure`. The directories are created
+    /// before `configure` is called.
+    pub async fn start_with<F: FnOnce(&mut Settings)>(configure: F) -> Result<TestDaemon, Error> {
+        let dir = tempfile::tempdir()?;
+        let mut settings = test_settings(dir.path())?;
+        configure(&mut settings);
+
+        let server = bind(&settings)?;
+        let addr = server.local_addr()?;
+        settings.port = addr.port() as usize;
+
+        runtime::spawn(async move {
+            if let Err(err) = server.serve_requests().await {
+                println!("test daemon failed: {}", err);
+            }
+        });
+
+        Ok(TestDaemon {
+            dir,
+            addr,
+            settings,
+        })
+    }
+
+    /// A client for this daemon, failing requests after 10 seconds.
+    pub fn client(&self) -> FilbaseClient {
+        FilbaseClient::new(self.client_config())
+    }
+
+    pub fn client_config(&self) -> ClientConfig {
+        ClientConfig::new(self.addr.to_string()).request_timeout(Duration::from_secs(10))
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The settings of the daemon, with the bound port.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// The temporary directory holding the daemon's directories.
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Writes a file into the temporary directory, e.g. to add it as a piece.
+    pub fn write_file(&self, name: &str, data: &[u8]) -> Result<PathBuf, Error> {
+        let path = self.dir.path().join(name);
+        fs::write(&path, data)?;
+        Ok(path)
+    }
+}
+
+/// A daemon which does not serve connections, handling requests directly, e.g. for fuzzing.
+///
+/// No background tasks are running, so nothing happens besides the handled requests.
+pub struct TestServer {
+    dir: TempDir,
+    server: Server,
+}
+
+impl TestServer {
+    pub fn new() -> Result<TestServer, Error> {
+        let dir = tempfile::tempdir()?;
+        let server = bind(&test_settings(dir.path())?)?;
+
+        Ok(TestServer { dir, server })
+    }
+
+    pub fn handle(&self, request: Request) -> Response {
+        self.server.handle(request)
+    }
+
+    /// The temporary directory holding the daemon's directories.
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// Settings on port 0, with the directories created in `dir`.
+fn test_settings(dir: &Path) -> Result<Settings, Error> {
+    let path = |name: &str| -> Result<String, Error> {
+        let path = dir.join(name);
+        fs::create_dir_all(&path)?;
+        Ok(path.to_string_lossy().into())
+    };
+
+    let mut settings = Settings::default();
+    settings.host = "127.0.0.1".into();
+    settings.port = 0;
+    settings.metadata_dir = path("meta")?;
+    settings.sealed_sector_dir = path("sealed")?;
+    settings.staged_sector_dir = path("staged")?;
+    settings.max_num_staged_sectors = 4;
+
+    Ok(settings)
+}
+
+fn bind(settings: &Settings) -> Result<Server, Error> {
+    Server::bind(settings, Some(PROVER_ID), SECTOR_SIZE, |cfg, prover_id| {
+        let backend = FakeBackend::new(
+            SECTOR_SIZE,
+            cfg.post_partitions,
+            cfg.max_num_staged_sectors,
+            0,
+            prover_id,
+            &cfg.sealed_sector_dir,
+        )?;
+        Ok(Box::new(backend))
+    })
+}
@@ -0,0 +1,390 @@
+//! Serde helpers for the binary fields of the protocol, used with `#[serde(with = "...")]`.
+//!
+//! The fields are encoded as CBOR byte strings. Decoding also accepts arrays of integers, the
+//! encoding used before protocol version 2, so that older clients get a proper version error.
+//! `Vec<u8>` fields use `serde_bytes`. The sector metadata of `filecoin-proofs` is mirrored by
+//! remote definitions, as its own encoding uses arrays.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use filecoin_proofs::api::sector_builder::metadata::{
+    PieceMetadata, SealStatus, SealedSectorMetadata, StagedSectorMetadata,
+};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+/// Fixed size arrays, like commitments and ids.
+pub mod array {
+    use super::*;
+
+    pub fn serialize<A, S>(array: &A, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        A: AsRef<[u8]>,
+        S: Serializer,
+    {
+        serializer.serialize_bytes(array.as_ref())
+    }
+
+    pub fn deserialize<'de, A, D>(deserializer: D) -> Result<A, D::Error>
+    where
+        A: Default + AsMut<[u8]>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(ArrayVisitor(PhantomData))
+    }
+}
+
+/// Optional fixed size arrays.
+pub mod option_array {
+    use super::*;
+
+    pub fn serialize<A, S>(array: &Option<A>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        A: AsRef<[u8]>,
+        S: Serializer,
+    {
+        match array {
+            Some(array) => serializer.serialize_some(&Bytes(array.as_ref())),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, A, D>(deserializer: D) -> Result<Option<A>, D::Error>
+    where
+        A: Default + AsMut<[u8]>,
+        D: Deserializer<'de>,
+    {
+        let array: Option<Array<A>> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(array.map(|Array(array)| array))
+    }
+}
+
+/// Lists of fixed size arrays, like the replica commitments of a PoSt.
+pub mod arrays {
+    use super::*;
+
+    pub fn serialize<A, S>(arrays: &[A], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        A: AsRef<[u8]>,
+        S: Serializer,
+    {
+        serializer.collect_seq(arrays.iter().map(|array| Bytes(array.as_ref())))
+    }
+
+    pub fn deserialize<'de, A, D>(deserializer: D) -> Result<Vec<A>, D::Error>
+    where
+        A: Default + AsMut<[u8]>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(ArraysVisitor(PhantomData))
+    }
+}
+
+/// Lists of byte vectors, like PoSt proofs.
+pub mod vecs {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(vecs: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(vecs.iter().map(|vec| Bytes(vec.as_slice())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        let bufs: Vec<serde_bytes::ByteBuf> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(bufs
+            .into_iter()
+            .map(serde_bytes::ByteBuf::into_vec)
+            .collect())
+    }
+}
+
+/// The metadata of a sealed sector.
+pub mod sealed_sector {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        meta: &SealedSectorMetadata,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        SealedSectorDef::serialize(meta, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SealedSectorMetadata, D::Error> {
+        SealedSectorDef::deserialize(deserializer)
+    }
+}
+
+/// Lists of the metadata of staged sectors.
+pub mod staged_sectors {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        metas: &[StagedSectorMetadata],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(metas.iter().map(Staged))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<StagedSectorMetadata>, D::Error> {
+        let metas: Vec<StagedOwned> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(metas.into_iter().map(|StagedOwned(meta)| meta).collect())
+    }
+}
+
+/// The seal status of a sector, which holds the metadata once it is sealed.
+pub mod seal_status {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(status: &SealStatus, serializer: S) -> Result<S::Ok, S::Error> {
+        SealStatusDef::serialize(status, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SealStatus, D::Error> {
+        SealStatusDef::deserialize(deserializer)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SealedSectorMetadata")]
+struct SealedSectorDef {
+    sector_id: u64,
+    sector_access: String,
+    pieces: Vec<PieceMetadata>,
+    #[serde(with = "array")]
+    comm_r_star: [u8; 32],
+    #[serde(with = "array")]
+    comm_r: [u8; 32],
+    #[serde(with = "array")]
+    comm_d: [u8; 32],
+    #[serde(with = "serde_bytes")]
+    proof: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    blake2b_checksum: Vec<u8>,
+    len: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "StagedSectorMetadata")]
+struct StagedSectorDef {
+    sector_id: u64,
+    sector_access: String,
+    pieces: Vec<PieceMetadata>,
+    #[serde(with = "SealStatusDef")]
+    seal_status: SealStatus,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "SealStatus")]
+enum SealStatusDef {
+    Failed(String),
+    Pending,
+    Sealed(#[serde(with = "boxed_sealed_sector")] Box<SealedSectorMetadata>),
+    Sealing,
+}
+
+mod boxed_sealed_sector {
+    use super::*;
+
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(
+        meta: &Box<SealedSectorMetadata>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        SealedSectorDef::serialize(meta, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<SealedSectorMetadata>, D::Error> {
+        SealedSectorDef::deserialize(deserializer).map(Box::new)
+    }
+}
+
+/// Serializes staged sector metadata inside a list.
+struct Staged<'a>(&'a StagedSectorMetadata);
+
+impl<'a> Serialize for Staged<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        StagedSectorDef::serialize(self.0, serializer)
+    }
+}
+
+/// Deserializes staged sector metadata inside a list.
+struct StagedOwned(StagedSectorMetadata);
+
+impl<'de> serde::Deserialize<'de> for StagedOwned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        StagedSectorDef::deserialize(deserializer).map(StagedOwned)
+    }
+}
+
+/// Serializes a slice as byte string.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct ArrayVisitor<A>(PhantomData<A>);
+
+impl<'de, A: Default + AsMut<[u8]>> Visitor<'de> for ArrayVisitor<A> {
+    type Value = A;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} bytes", A::default().as_mut().len())
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<A, E> {
+        let mut array = A::default();
+        if bytes.len() != array.as_mut().len() {
+            return Err(E::invalid_length(bytes.len(), &self));
+        }
+        array.as_mut().copy_from_slice(bytes);
+        Ok(array)
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<A, S::Error> {
+        let mut array = A::default();
+        let len = array.as_mut().len();
+        for (i, byte) in array.as_mut().iter_mut().enumerate() {
+            *byte = match seq.next_element()? {
+                Some(byte) => byte,
+                None => return Err(de::Error::invalid_length(i, &self)),
+            };
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(de::Error::invalid_length(len + 1, &self));
+        }
+        Ok(array)
+    }
+}
+
+struct ArraysVisitor<A>(PhantomData<A>);
+
+/// Deserializes a single array inside a list.
+struct Array<A>(A);
+
+impl<'de, A: Default + AsMut<[u8]>> serde::Deserialize<'de> for Array<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        array::deserialize(deserializer).map(Array)
+    }
+}
+
+impl<'de, A: Default + AsMut<[u8]>> Visitor<'de> for ArraysVisitor<A> {
+    type Value = Vec<A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a list of byte arrays")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Vec<A>, S::Error> {
+        // Like serde, don't trust the announced length for the allocation.
+        let mut arrays = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(Array(array)) = seq.next_element()? {
+            arrays.push(array);
+        }
+        Ok(arrays)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Binary {
+        #[serde(with = "super::array")]
+        id: [u8; 31],
+        #[serde(with = "super::arrays")]
+        comms: Vec<[u8; 32]>,
+        #[serde(with = "super::vecs")]
+        proofs: Vec<Vec<u8>>,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+    }
+
+    /// The encoding before protocol version 2.
+    #[derive(Serialize)]
+    struct Arrays {
+        id: [u8; 31],
+        comms: Vec<[u8; 32]>,
+        proofs: Vec<Vec<u8>>,
+        data: Vec<u8>,
+    }
+
+    fn binary() -> Binary {
+        Binary {
+            id: [1; 31],
+            comms: vec![[2; 32], [3; 32]],
+            proofs: vec![vec![4; 10], vec![]],
+            data: vec![5; 100],
+        }
+    }
+
+    #[test]
+    fn byte_strings() {
+        let encoded = serde_cbor::to_vec(&binary()).unwrap();
+        assert_eq!(binary(), serde_cbor::from_slice(&encoded).unwrap());
+
+        let value: serde_cbor::Value = serde_cbor::from_slice(&encoded).unwrap();
+        let map = match value {
+            serde_cbor::Value::Object(map) => map,
+            value => panic!("unexpected value {:?}", value),
+        };
+        for (key, value) in map {
+            match value {
+                serde_cbor::Value::Bytes(_) => {}
+                serde_cbor::Value::Array(ref values) => assert!(values.iter().all(|v| match v {
+                    serde_cbor::Value::Bytes(_) => true,
+                    _ => false,
+                })),
+                value => panic!("{:?} is encoded as {:?}", key, value),
+            }
+        }
+    }
+
+    #[test]
+    fn decode_arrays() {
+        let arrays = Arrays {
+            id: [1; 31],
+            comms: vec![[2; 32], [3; 32]],
+            proofs: vec![vec![4; 10], vec![]],
+            data: vec![5; 100],
+        };
+        let encoded = serde_cbor::to_vec(&arrays).unwrap();
+        assert_eq!(binary(), serde_cbor::from_slice(&encoded).unwrap());
+    }
+
+    #[test]
+    fn invalid_length() {
+        #[derive(Serialize)]
+        struct Short {
+            #[serde(with = "serde_bytes")]
+            id: Vec<u8>,
+            comms: Vec<[u8; 0]>,
+            proofs: Vec<Vec<u8>>,
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+        }
+
+        for len in &[30, 32] {
+            let short = Short {
+                id: vec![1; *len],
+                comms: vec![],
+                proofs: vec![],
+                data: vec![],
+            };
+            let encoded = serde_cbor::to_vec(&short).unwrap();
+            assert!(serde_cbor::from_slice::<Binary>(&encoded).is_err());
+        }
+    }
+}
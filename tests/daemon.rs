@@ -0,0 +1,484 @@
+#![feature(async_await)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use filbase::api::*;
+use filbase::backend::sector_id_as_bytes;
+use filbase::cbor_codec::Codec;
+use filbase::client::ClientError;
+use filbase::testing::{TestDaemon, TestServer, PROVER_ID, SECTOR_SIZE};
+use filbase::{ClientConfig, FilbaseClient};
+
+use filecoin_proofs::api::sector_builder::metadata::SealStatus;
+use futures::prelude::*;
+use futures_codec::Framed;
+use runtime::net::{TcpListener, TcpStream};
+
+async fn add_piece(daemon: &TestDaemon, client: &FilbaseClient, key: &str, len: usize) -> u64 {
+    let path = daemon
+        .write_file(key, &vec![key.as_bytes()[0]; len])
+        .unwrap();
+    client
+        .piece_add(key, None, path.to_string_lossy())
+        .await
+        .unwrap()
+        .sector_id
+}
+
+fn is_server_error(err: &failure::Error) -> bool {
+    match err.downcast_ref::<ClientError>() {
+        Some(ClientError::Server(_)) => true,
+        _ => false,
+    }
+}
+
+#[runtime::test]
+async fn status_and_sizes() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    let status = client.status().await.unwrap();
+    assert_eq!(status.prover_id, PROVER_ID);
+    assert_eq!(status.sector_size, SECTOR_SIZE);
+    assert_eq!(status.max_user_bytes, 1016);
+    assert!(status.faults.is_empty());
+    assert_eq!(status.disks.len(), 3);
+
+    assert_eq!(client.sector_size(SECTOR_SIZE).await.unwrap(), 1016);
+}
+
+#[runtime::test]
+async fn pieces() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    assert_eq!(add_piece(&daemon, &client, "a", 100).await, 1);
+    assert_eq!(add_piece(&daemon, &client, "b", 200).await, 1);
+
+    let path = daemon.write_file("dup", &[0; 10]).unwrap();
+    let err = client
+        .piece_add("a", None, path.to_string_lossy())
+        .await
+        .unwrap_err();
+    assert!(is_server_error(&err));
+    let err = client
+        .piece_add("missing-file", None, "/does/not/exist")
+        .await
+        .unwrap_err();
+    assert!(is_server_error(&err));
+
+    let list = client.piece_list(None, 0, 10, false).await.unwrap();
+    assert_eq!(list.total, 2);
+    assert_eq!(list.pieces[0].key, "a");
+    let list = client
+        .piece_list(Some("b".into()), 0, 10, false)
+        .await
+        .unwrap();
+    assert_eq!(list.total, 1);
+    let list = client.piece_list(None, 1, 10, false).await.unwrap();
+    assert_eq!(list.pieces.len(), 1);
+
+    let info = client.piece_info("b").await.unwrap();
+    assert_eq!(info.num_bytes, 200);
+    assert_eq!(info.sector_id, 1);
+    match info.sector_state {
+        SectorState::Staged => {}
+        state => panic!("unexpected state {:?}", state),
+    }
+    assert!(client.piece_info("unknown").await.is_err());
+
+    let usage = client.sector_usage(Some(1000)).await.unwrap();
+    assert_eq!(usage.used_bytes, 300);
+    assert_eq!(usage.fits, Some(true));
+    assert_eq!(usage.sectors.len(), 1);
+    assert_eq!(usage.sectors[0].fits, Some(false));
+
+    // Staged pieces can not be expired or read.
+    assert!(client.piece_read("a").await.is_err());
+    assert!(client.piece_expire("a").await.is_err());
+    assert!(client.piece_expire("unknown").await.is_err());
+
+    assert_eq!(
+        client.piece_list(None, 0, 10, false).await.unwrap().total,
+        2
+    );
+    assert_eq!(client.sector_usage(None).await.unwrap().used_bytes, 300);
+}
+
+#[runtime::test]
+async fn sealing() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    assert_eq!(add_piece(&daemon, &client, "a", 1000).await, 1);
+    assert_eq!(add_piece(&daemon, &client, "b", 1000).await, 2);
+    assert_eq!(client.sector_list_staged().await.unwrap().len(), 2);
+
+    match client.seal_status(1).await.unwrap() {
+        SealStatus::Pending => {}
+        status => panic!("unexpected status {:?}", status),
+    }
+    assert!(client.seal_status(42).await.is_err());
+
+    client.seal_all_staged().await.unwrap();
+    match client.seal_status(1).await.unwrap() {
+        SealStatus::Sealed(_) => {}
+        status => panic!("unexpected status {:?}", status),
+    }
+
+    assert_eq!(client.piece_read("a").await.unwrap(), vec![b'a'; 1000]);
+    assert!(client.piece_read("unknown").await.is_err());
+
+    client.piece_expire("a").await.unwrap();
+    assert!(client.piece_read("a").await.is_err());
+    assert!(client.piece_expire("a").await.is_err());
+    let path = daemon.write_file("a", b"again").unwrap();
+    assert!(client
+        .piece_add("a", None, path.to_string_lossy())
+        .await
+        .is_err());
+
+    assert_eq!(add_piece(&daemon, &client, "c", 10).await, 3);
+    client.seal_all_staged().await.unwrap();
+
+    let sealed = client.sector_list_sealed().await.unwrap();
+    assert_eq!(sealed.len(), 3);
+}
+
+#[runtime::test]
+async fn seal_proofs() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    add_piece(&daemon, &client, "a", 100).await;
+    client.seal_all_staged().await.unwrap();
+    let meta = client.sector_list_sealed().await.unwrap().remove(0).meta;
+
+    match client.seal_verify_sector(meta.sector_id).await.unwrap() {
+        SealVerifyResult::Valid => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    match client.seal_verify_sector(42).await.unwrap() {
+        SealVerifyResult::Invalid { field, .. } => assert_eq!(field, Some("seal_status".into())),
+        res => panic!("unexpected result {:?}", res),
+    }
+
+    let verify = |proof: Vec<u8>, prover_id: [u8; 31]| {
+        client.seal_verify(
+            SECTOR_SIZE,
+            meta.comm_r,
+            meta.comm_d,
+            meta.comm_r_star,
+            prover_id,
+            sector_id_as_bytes(meta.sector_id),
+            proof,
+        )
+    };
+    assert!(verify(meta.proof.clone(), PROVER_ID).await.unwrap());
+    assert!(!verify(meta.proof.clone(), [2; 31]).await.unwrap());
+    assert!(!verify(vec![0; 32], PROVER_ID).await.unwrap());
+}
+
+#[runtime::test]
+async fn post() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    add_piece(&daemon, &client, "a", 1000).await;
+    add_piece(&daemon, &client, "b", 1000).await;
+    client.seal_all_staged().await.unwrap();
+
+    let comm_rs: Vec<_> = client
+        .sector_list_sealed()
+        .await
+        .unwrap()
+        .iter()
+        .map(|s| s.meta.comm_r)
+        .collect();
+    let seed = [5; 32];
+
+    let post = client.post_generate(comm_rs.clone(), seed).await.unwrap();
+    assert!(post.faults.is_empty());
+    let valid = client
+        .post_verify(
+            SECTOR_SIZE,
+            post.proofs.len() as u8,
+            comm_rs.clone(),
+            seed,
+            post.proofs.clone(),
+            post.faults.clone(),
+        )
+        .await
+        .unwrap();
+    assert!(valid);
+
+    // A different challenge seed, or garbage proofs, do not verify.
+    let valid = client
+        .post_verify(
+            SECTOR_SIZE,
+            post.proofs.len() as u8,
+            comm_rs.clone(),
+            [6; 32],
+            post.proofs.clone(),
+            vec![],
+        )
+        .await
+        .unwrap();
+    assert!(!valid);
+    let valid = client
+        .post_verify(
+            SECTOR_SIZE,
+            post.proofs.len() as u8,
+            comm_rs.clone(),
+            seed,
+            vec![vec![0; 32]; post.proofs.len()],
+            vec![],
+        )
+        .await
+        .unwrap();
+    assert!(!valid);
+
+    assert!(client.post_generate(vec![[9; 32]], seed).await.is_err());
+
+    let sealed = client
+        .post_generate_sealed(SectorFilter::Ids(vec![2]), seed)
+        .await
+        .unwrap();
+    assert_eq!(sealed.sector_ids, vec![2]);
+    assert_eq!(sealed.comm_rs, vec![comm_rs[1]]);
+    let valid = client
+        .post_verify(
+            sealed.sector_size,
+            sealed.proof_partitions,
+            sealed.comm_rs,
+            sealed.challenge_seed,
+            sealed.proofs,
+            sealed.faults,
+        )
+        .await
+        .unwrap();
+    assert!(valid);
+
+    let range = SectorFilter::Range { start: 5, end: 9 };
+    assert!(client.post_generate_sealed(range, seed).await.is_err());
+
+    // Sectors with recorded faults are left out, so the proof still verifies.
+    let sealed = client.sector_list_sealed().await.unwrap();
+    std::fs::write(&sealed[0].meta.sector_access, b"corrupt").unwrap();
+    assert_eq!(client.sector_check(false).await.unwrap().len(), 1);
+
+    let sealed = client
+        .post_generate_sealed(SectorFilter::All, seed)
+        .await
+        .unwrap();
+    assert_eq!(sealed.sector_ids, vec![2]);
+    assert_eq!(sealed.excluded, vec![1]);
+    let valid = client
+        .post_verify(
+            sealed.sector_size,
+            sealed.proof_partitions,
+            sealed.comm_rs,
+            sealed.challenge_seed,
+            sealed.proofs,
+            sealed.faults,
+        )
+        .await
+        .unwrap();
+    assert!(valid);
+
+    let only_faulty = SectorFilter::Ids(vec![1]);
+    assert!(client
+        .post_generate_sealed(only_faulty, seed)
+        .await
+        .is_err());
+}
+
+#[runtime::test]
+async fn sectors() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    add_piece(&daemon, &client, "a", 100).await;
+    client.seal_all_staged().await.unwrap();
+
+    assert!(client.sector_check(true).await.unwrap().is_empty());
+
+    let other = daemon.dir().join("other");
+    let to = other.to_string_lossy().into_owned();
+    let err = client
+        .sector_move(SectorMove::Sector { sector_id: 1, to })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("not a configured storage path"));
+
+    let archive = daemon.dir().join("sector-1.tar");
+    client
+        .sector_export(1, archive.to_string_lossy())
+        .await
+        .unwrap();
+    assert!(archive.exists());
+    assert!(client
+        .sector_export(1, archive.to_string_lossy())
+        .await
+        .is_err());
+    assert!(client
+        .sector_export(42, daemon.dir().join("sector-42.tar").to_string_lossy())
+        .await
+        .is_err());
+
+    // Removing the sealed file is found by the check.
+    let sealed = client.sector_list_sealed().await.unwrap();
+    std::fs::remove_file(&sealed[0].meta.sector_access).unwrap();
+    let faults = client.sector_check(false).await.unwrap();
+    assert_eq!(faults.len(), 1);
+    assert_eq!(faults[0].sector_id, 1);
+}
+
+#[runtime::test]
+async fn backup() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    let archive = daemon.dir().join("backup.tar");
+    client
+        .backup_create(archive.to_string_lossy())
+        .await
+        .unwrap();
+    assert!(std::fs::metadata(&archive).unwrap().len() > 0);
+    assert!(client
+        .backup_create(archive.to_string_lossy())
+        .await
+        .is_err());
+}
+
+#[runtime::test]
+async fn raw_requests() {
+    let daemon = TestDaemon::start().await.unwrap();
+    let client = daemon.client();
+
+    match client.send(Request::SectorListStaged).await.unwrap() {
+        Response::SectorListStaged(list) => assert!(list.is_empty()),
+        res => panic!("unexpected response {:?}", res),
+    }
+
+    let err = client
+        .send(Request::PieceInfo("unknown".into()))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("unknown piece"));
+}
+
+#[runtime::test]
+async fn connection_errors() {
+    let daemon = TestDaemon::start().await.unwrap();
+
+    // Nothing listens on port 1.
+    let mut config = daemon.client_config();
+    config.endpoint = "127.0.0.1:1".into();
+    let client = FilbaseClient::new(config.retries(1, std::time::Duration::from_millis(1)));
+    assert!(client.status().await.is_err());
+
+    // Requests on a shared client reuse the connection.
+    let client = daemon.client();
+    for _ in 0..3 {
+        client.status().await.unwrap();
+    }
+}
+
+/// Serves a daemon which closes its `i`th connection after answering `answered[i]` messages,
+/// including the handshake, as if it was restarted. Later connections are served until closed.
+fn restarting_daemon(answered: Vec<usize>) -> SocketAddr {
+    let server = TestServer::new().unwrap();
+    let mut listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    runtime::spawn(async move {
+        let mut incoming = listener.incoming();
+        let mut i = 0;
+        while let Some(Ok(stream)) = incoming.next().await {
+            let limit = answered.get(i).cloned().unwrap_or(usize::max_value());
+            let mut framed = Framed::new(stream, Codec::<Request, Response>::new());
+            for _ in 0..limit {
+                match framed.next().await {
+                    Some(Ok(request)) => {
+                        if framed.send(server.handle(request)).await.is_err() {
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+            i += 1;
+        }
+    });
+
+    addr
+}
+
+#[runtime::test]
+async fn daemon_restarts() {
+    let addr = restarting_daemon(vec![2, 0, 2, 0]);
+    let config = ClientConfig::new(addr.to_string()).retries(3, Duration::from_millis(1));
+    let client = FilbaseClient::new(config);
+    client.status().await.unwrap();
+
+    // The pooled connection was closed after the request was written, and the next one before
+    // the handshake. Idempotent requests are sent again in both cases.
+    client.status().await.unwrap();
+
+    // The daemon may have received a request on the closed connection, so others are not sent
+    // again.
+    assert!(client.seal_all_staged().await.is_err());
+
+    // Requests which were not written yet are retried.
+    client.seal_all_staged().await.unwrap();
+}
+
+async fn connect(addr: SocketAddr) -> Framed<TcpStream, Codec<Response, Request>> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    Framed::new(stream, Codec::new())
+}
+
+#[runtime::test]
+async fn handshake() {
+    let daemon = TestDaemon::start().await.unwrap();
+
+    // Requests before the handshake are rejected.
+    let mut framed = connect(daemon.addr()).await;
+    framed.send(Request::Status).await.unwrap();
+    match framed.next().await {
+        Some(Ok(Response::Err(err))) => assert!(err.contains("expected a handshake")),
+        res => panic!("unexpected response {:?}", res),
+    }
+    assert!(framed.next().await.is_none());
+
+    // Other versions get the version of the daemon, before the connection is closed.
+    let mut framed = connect(daemon.addr()).await;
+    let handshake = Request::Handshake {
+        protocol_version: 1,
+    };
+    framed.send(handshake).await.unwrap();
+    match framed.next().await {
+        Some(Ok(Response::Handshake { protocol_version })) => {
+            assert_eq!(protocol_version, PROTOCOL_VERSION)
+        }
+        res => panic!("unexpected response {:?}", res),
+    }
+    assert!(framed.next().await.is_none());
+
+    let mut framed = connect(daemon.addr()).await;
+    let handshake = Request::Handshake {
+        protocol_version: PROTOCOL_VERSION,
+    };
+    framed.send(handshake).await.unwrap();
+    match framed.next().await {
+        Some(Ok(Response::Handshake { .. })) => {}
+        res => panic!("unexpected response {:?}", res),
+    }
+    framed.send(Request::Status).await.unwrap();
+    match framed.next().await {
+        Some(Ok(Response::Status(status))) => assert_eq!(status.prover_id, PROVER_ID),
+        res => panic!("unexpected response {:?}", res),
+    }
+}
@@ -0,0 +1,50 @@
+//! Replays the inputs in `fuzz/regressions/` through the bodies of the fuzz targets.
+
+use std::fs;
+use std::path::PathBuf;
+
+use filbase::fuzz;
+use filbase::testing::TestServer;
+
+fn inputs(target: &str) -> Vec<(PathBuf, Vec<u8>)> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fuzz/regressions")
+        .join(target);
+
+    let mut inputs: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            (path, data)
+        })
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty());
+    inputs
+}
+
+#[test]
+fn decode() {
+    for (path, data) in inputs("decode") {
+        println!("{}", path.display());
+        fuzz::decode(&data);
+    }
+}
+
+#[test]
+fn request() {
+    for (path, data) in inputs("request") {
+        println!("{}", path.display());
+        fuzz::request(&data);
+    }
+}
+
+#[test]
+fn respond() {
+    for (path, data) in inputs("respond") {
+        println!("{}", path.display());
+        let server = TestServer::new().unwrap();
+        fuzz::respond(&server, &data);
+    }
+}